@@ -1,26 +1,48 @@
-use std::collections::BTreeMap;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    io::Read,
+    net::TcpStream,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc,
+    },
+    time::{Duration, Instant},
+};
 
+use common::QServerTime;
 use serde::{Deserialize, Deserializer, Serialize, de::DeserializeOwned};
-use session::Logon;
+use session::{Logon, SignedLogon};
 use sproxy::ProxyConfig;
 use tracing::{debug, warn};
+use user_stream::{
+    SubscribeUserStream, SubscribeUserStreamSignature, UnsubscribeUserStream,
+    UserStreamSubscription,
+};
 use ws_tool::{
     ClientBuilder,
-    codec::{StringCodec, StringRecv, StringSend},
+    codec::{FrameConfig, FrameReadState, FrameWriteState, Split, StringRecv, StringSend},
     connector::{tcp_connect, wrap_rustls},
     errors::WsError,
     frame::OpCode,
+    protocol::standard_handshake_resp_check,
     stream::{SyncStream, SyncStreamRead, SyncStreamWrite},
 };
 
-use crate::millis_ts;
-
 pub const BASE_SPOT_URL: &str = "wss://ws-api.binance.com:443/ws-api/v3";
 pub const TEST_SPOT_URL: &str = "wss://testnet.binance.vision/ws-api/v3";
 pub const BASE_FUTURE_URL: &str = "wss://ws-fapi.binance.com/ws-fapi/v1";
 pub const TEST_FUTURE_URL: &str = "wss://testnet.binancefuture.com/ws-fapi/v1";
 pub const BACKUP_PORT: u16 = 9443;
 
+#[cfg(feature = "async")]
+pub use async_client::AsyncClient;
+pub use clock::ClockSync;
+pub use error::{BinanceError, ErrorCategory, ErrorCode};
+use rate_limit::Permit;
+pub use rate_limit::{RateLimitMode, RateLimiter};
+pub use sign::{Ed25519Signer, HmacSigner, KeyType, RequestSigner, RsaSigner, SignError, Signer};
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Request<T> {
@@ -37,16 +59,20 @@ pub struct RawResponse<T> {
     pub id: u64,
     pub status: i64,
     pub result: Option<T>,
-    pub error: Option<serde_json::Value>,
+    pub error: Option<BinanceError>,
     #[serde(rename = "rateLimits")]
     pub rate_limits: Vec<RateLimit>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ErrResponse {
+    /// 无法解析请求时服务器返回 `null`, 此时为 0
+    #[serde(default, deserialize_with = "null_as_zero")]
     pub id: u64,
-    pub error: serde_json::Value,
+    pub error: BinanceError,
     pub status: i64,
+    #[serde(default, rename = "rateLimits")]
+    pub rate_limits: Vec<RateLimit>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -54,9 +80,58 @@ pub struct ErrResponse {
 pub struct Response<T> {
     pub id: i64,
     pub result: T,
+    #[serde(default)]
     pub rate_limits: Vec<RateLimit>,
 }
 
+/// 服务器主动推送的事件, 如 `userDataStream.subscribe` 之后的账户事件
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EventFrame {
+    #[serde(default)]
+    pub subscription_id: Option<i64>,
+    pub event: serde_json::Value,
+}
+
+impl EventFrame {
+    /// 解析为具体的事件类型, 如 [`UserStreamData`](crate::realtime_market::UserStreamData)
+    pub fn parse<T: DeserializeOwned>(&self) -> Result<T, serde_json::Error> {
+        T::deserialize(&self.event)
+    }
+}
+
+/// 不属于任何请求的消息
+#[derive(Debug, Clone)]
+pub enum ServerMessage {
+    /// 订阅的推送事件
+    Event(EventFrame),
+    /// 没有请求 id 的错误, 如服务器无法解析的请求
+    Error(ErrResponse),
+    /// 其他无法识别的消息
+    Unknown(serde_json::Value),
+}
+
+/// 推送消息的处理函数, 在读取线程中调用, 不应长时间阻塞
+pub type EventHandler = Arc<dyn Fn(ServerMessage) + Send + Sync>;
+
+/// 可以在连接建立后设置的事件处理函数, 未设置时丢弃事件
+#[derive(Clone, Default)]
+struct Events(Arc<Mutex<Option<EventHandler>>>);
+
+impl Events {
+    fn set(&self, handler: EventHandler) {
+        *self.0.lock().unwrap() = Some(handler);
+    }
+
+    fn dispatch(&self, msg: ServerMessage) {
+        let handler = self.0.lock().unwrap().clone();
+        match handler {
+            Some(handler) => handler(msg),
+            None => debug!("drop message without handler {msg:?}"),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct RateLimit {
@@ -72,10 +147,76 @@ pub struct RateLimit {
 pub enum ClientError {
     #[error("{0}")]
     WsError(WsError),
-    #[error("{0:?}")]
+    #[error("api error {}: {}", .0.status, .0.error)]
     ApiError(ErrResponse),
     #[error("{0}")]
     SerdeError(serde_json::Error),
+    #[error("request timeout")]
+    Timeout,
+    #[error("rate limited, retry after {0:?}")]
+    RateLimited(Duration),
+    /// 单个请求的消耗超过限制, 等待也无法发送
+    #[error("request cost {cost} exceeds rate limit {limit}")]
+    ExceedsRateLimit { cost: i64, limit: i64 },
+    #[error("{0}")]
+    SignError(SignError),
+    #[error("{0}")]
+    HttpError(reqwest::Error),
+}
+
+impl ClientError {
+    /// 接口返回的错误
+    pub fn api_error(&self) -> Option<&BinanceError> {
+        match self {
+            ClientError::ApiError(err) => Some(&err.error),
+            _ => None,
+        }
+    }
+
+    /// 请求确定未执行, 可以直接重试
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            ClientError::ApiError(err) => err.error.is_retryable(),
+            ClientError::HttpError(e) => e.is_connect(),
+            _ => false,
+        }
+    }
+
+    /// 请求可能已经执行, 如连接断开或等待响应超时, 下单和撤单需要先查询状态再决定是否重试
+    pub fn is_unknown_outcome(&self) -> bool {
+        match self {
+            ClientError::WsError(_) | ClientError::Timeout => true,
+            ClientError::ApiError(err) => err.error.is_unknown_outcome(),
+            ClientError::HttpError(e) => !e.is_connect() && (e.is_timeout() || e.is_request()),
+            ClientError::SerdeError(_)
+            | ClientError::RateLimited(_)
+            | ClientError::ExceedsRateLimit { .. }
+            | ClientError::SignError(_) => false,
+        }
+    }
+
+    pub fn is_auth(&self) -> bool {
+        self.api_error().is_some_and(|e| e.is_auth())
+    }
+
+    pub fn is_filter_violation(&self) -> bool {
+        self.api_error().is_some_and(|e| e.is_filter_violation())
+    }
+
+    /// 本地限频或服务器返回的限频错误(包括 429/418)
+    pub fn is_rate_limit(&self) -> bool {
+        match self {
+            ClientError::RateLimited(_) => true,
+            ClientError::ApiError(err) => {
+                err.status == 429 || err.status == 418 || err.error.is_rate_limit()
+            }
+            _ => false,
+        }
+    }
+
+    pub fn is_order_rejected(&self) -> bool {
+        self.api_error().is_some_and(|e| e.is_order_rejected())
+    }
 }
 
 impl From<WsError> for ClientError {
@@ -84,101 +225,210 @@ impl From<WsError> for ClientError {
     }
 }
 
+impl From<SignError> for ClientError {
+    fn from(value: SignError) -> Self {
+        Self::SignError(value)
+    }
+}
+
+impl From<reqwest::Error> for ClientError {
+    fn from(value: reqwest::Error) -> Self {
+        Self::HttpError(value)
+    }
+}
+
 impl From<serde_json::Error> for ClientError {
     fn from(value: serde_json::Error) -> Self {
         Self::SerdeError(value)
     }
 }
 
+/// 参数为重建连接的截止时间
+type ConnFn = Box<dyn FnMut(Instant) -> Result<SyncStream, WsError> + Send>;
+type Reply = mpsc::Sender<Result<String, ClientError>>;
+
+/// 后台读取线程检查连接是否关闭的间隔
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// 重建连接后登录和重新订阅的默认超时
+const RECONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// [`Client`] 最多记录的已超时请求数量
+const MAX_EXPIRED: usize = 1024;
+
+/// 自动重连的客户端
+///
+/// 后台线程负责读取响应并按 id 分发给等待中的请求, 可以在多个线程间共享
 pub struct AutoReconnectClient {
-    req_id: u64,
+    req_id: AtomicU64,
     auth: Option<Logon>,
-    conn_fn: Box<dyn FnMut() -> Result<StringCodec<SyncStream>, WsError>>,
+    conn_fn: Mutex<ConnFn>,
     recv_window: i64,
-    messages: BTreeMap<u64, String>,
-    stream: Option<StringCodec<SyncStream>>,
+    conn: Mutex<Option<Arc<Connection>>>,
+    limiter: Option<Arc<RateLimiter>>,
+    clock: Arc<ClockSync>,
+    signer: Option<RequestSigner>,
+    events: Events,
+    /// 重连后需要重新订阅用户数据
+    user_stream: AtomicBool,
 }
 
 impl AutoReconnectClient {
-    pub fn new(
-        auth: Option<Logon>,
-        conn_fn: Box<dyn FnMut() -> Result<StringCodec<SyncStream>, WsError>>,
-        recv_window: i64,
-    ) -> Self {
+    /// `conn_fn` 需要在截止时间前返回已完成 websocket 握手的连接,
+    /// HMAC 和 RSA key 无法登录, 自动改为逐个请求签名
+    pub fn new(auth: Option<Logon>, conn_fn: ConnFn, recv_window: i64) -> Self {
+        let signer = auth
+            .as_ref()
+            .filter(|auth| auth.key_type != KeyType::Ed25519)
+            .and_then(|auth| {
+                auth.request_signer()
+                    .inspect_err(|e| warn!("failed to create signer {e}"))
+                    .ok()
+            });
         Self {
-            req_id: 0,
+            req_id: AtomicU64::new(0),
             auth,
-            conn_fn,
+            conn_fn: Mutex::new(conn_fn),
             recv_window,
-            messages: Default::default(),
-            stream: None,
+            conn: Mutex::new(None),
+            limiter: None,
+            clock: Default::default(),
+            signer,
+            events: Default::default(),
+            user_stream: AtomicBool::new(false),
         }
     }
 
-    fn wrap<P>(&mut self, ty: QueryType, param: P) -> ParamWrapper<P> {
-        match ty {
+    /// 使用校准后的时间签名, 多个客户端可以共享同一个时钟
+    pub fn with_clock(mut self, clock: Arc<ClockSync>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn clock(&self) -> &Arc<ClockSync> {
+        &self.clock
+    }
+
+    /// 立即校准时间, 返回新的偏移(ms)
+    pub fn sync_clock(&self) -> Result<i64, ClientError> {
+        self.clock
+            .sync(|| self.query(QServerTime).map(|resp| resp.result.time))
+    }
+
+    /// 发送请求前检查限频, 多个客户端可以共享同一个限频器
+    pub fn with_rate_limiter(mut self, limiter: Arc<RateLimiter>) -> Self {
+        self.limiter = Some(limiter);
+        self
+    }
+
+    /// 逐个请求签名, 不依赖 session.logon
+    pub fn with_signer(mut self, signer: RequestSigner) -> Self {
+        self.signer = Some(signer);
+        self
+    }
+
+    /// 处理不属于任何请求的消息, 如订阅的用户数据
+    pub fn with_event_handler(
+        self,
+        handler: impl Fn(ServerMessage) + Send + Sync + 'static,
+    ) -> Self {
+        self.events.set(Arc::new(handler));
+        self
+    }
+
+    /// 订阅用户数据, 事件交给 [`AutoReconnectClient::with_event_handler`] 设置的处理函数,
+    /// 重连后自动重新订阅. 使用逐个请求签名时改用 `userDataStream.subscribe.signature`
+    pub fn subscribe_user_stream(&self) -> ApiResult<UserStreamSubscription> {
+        let resp = match self.signer {
+            Some(_) => self.query(SubscribeUserStreamSignature)?,
+            None => self.query(SubscribeUserStream)?,
+        };
+        self.user_stream.store(true, Ordering::Relaxed);
+        Ok(resp)
+    }
+
+    /// 取消当前连接上的所有用户数据订阅
+    pub fn unsubscribe_user_stream(&self) -> ApiResult<EmptyResponse> {
+        self.user_stream.store(false, Ordering::Relaxed);
+        self.query(UnsubscribeUserStream::default())
+    }
+
+    fn wrap<P: Serialize>(&self, ty: QueryType, param: P) -> Result<ParamWrapper<P>, ClientError> {
+        let param = match ty {
             QueryType::None => ParamWrapper {
                 recv_window: None,
                 api_key: None,
                 timestamp: 0,
+                signature: None,
                 other: param,
             },
             QueryType::Authorized => ParamWrapper {
                 recv_window: Some(self.recv_window),
                 api_key: Some(self.auth.clone().map(|a| a.api_key).unwrap_or_default()),
-                timestamp: millis_ts(),
+                timestamp: self.clock.now(),
+                signature: None,
                 other: param,
             },
             QueryType::AuthWithoutApiKey => ParamWrapper {
                 recv_window: Some(self.recv_window),
                 api_key: None,
-                timestamp: millis_ts(),
+                timestamp: self.clock.now(),
+                signature: None,
                 other: param,
             },
+        };
+        match (&self.signer, ty) {
+            (Some(signer), QueryType::Authorized | QueryType::AuthWithoutApiKey) => {
+                Ok(param.sign(signer)?)
+            }
+            _ => Ok(param),
         }
     }
 
     pub fn client(
-        url: &'static str,
+        url: &str,
         max_retries: usize,
         proxy: Option<ProxyConfig>,
         recv_window: i64,
         auth: Option<Logon>,
     ) -> Self {
+        let url = url.to_string();
         let mut try_times = 0;
-        let conn_single = move || {
-            let url = url::Url::parse(url).map_err(|e| WsError::InvalidUri(e.to_string()))?;
+        let conn_single = move |deadline: Instant| -> Result<SyncStream, WsError> {
+            let url = url::Url::parse(&url).map_err(|e| WsError::InvalidUri(e.to_string()))?;
             let stream = match proxy.clone() {
                 Some(config) => {
                     sproxy::create_conn(
                         &config,
                         url.host_str().unwrap().into(),
-                        url.port().unwrap_or(443),
+                        url.port_or_known_default().unwrap_or(443),
                     )?
                     .0
                 }
-                None => tcp_connect(&url.as_str().parse().unwrap())?,
+                None => connect_before(&url, deadline)?,
             };
             tracing::debug!(
                 "socket info local {:?} remote {:?}",
                 stream.local_addr(),
                 stream.peer_addr()
             );
-            let stream = wrap_rustls(stream, url.host_str().unwrap(), vec![])?;
-            ClientBuilder::new().with_stream(
-                url.as_str().parse().unwrap(),
-                SyncStream::Rustls(stream),
-                StringCodec::check_fn,
-            )
+            // 握手同样受截止时间限制, 完成后由 `split_stream` 重新设置
+            let timeout = remaining(deadline)?;
+            stream.set_read_timeout(Some(timeout))?;
+            stream.set_write_timeout(Some(timeout))?;
+            let stream = handshake(&url, stream)?;
+            match &stream {
+                SyncStream::Raw(s) => s.set_write_timeout(None)?,
+                SyncStream::Rustls(s) => s.sock.set_write_timeout(None)?,
+            }
+            Ok(stream)
         };
-        let conn_fn = move || loop {
+        let conn_fn = move |deadline: Instant| loop {
             if try_times >= max_retries {
                 return Err(WsError::ConnectionFailed(format!(
                     "try to connect over {} times",
                     max_retries
                 )));
             }
-            match conn_single() {
+            match conn_single(deadline) {
                 Ok(s) => {
                     try_times = 0;
                     return Ok(s);
@@ -186,176 +436,587 @@ impl AutoReconnectClient {
                 Err(e) => {
                     try_times += 1;
                     tracing::warn!("{e}");
-                    std::thread::sleep(std::time::Duration::from_millis(100));
+                    let wait = remaining(deadline)?.min(Duration::from_millis(100));
+                    std::thread::sleep(wait);
                 }
             }
         };
         Self::new(auth, Box::new(conn_fn), recv_window)
     }
 
-    // pub fn batch_query<P: ApiQuery>(
-    //     &mut self,
-    //     batch_size: usize,
-    //     params: Vec<P>,
-    // ) -> Result<Vec<Response<P::Response>>, ClientError> {
-    //     let mut req_ids = vec![];
-    //     let mut results = vec![];
-    //     let total = params.len();
-    //     for (idx, p) in params.into_iter().enumerate() {
-    //         let param = self.wrap(P::TYPE, p);
-    //         req_ids.push(self._send(P::METHOD, &param)?);
-    //         if (idx != 0 && idx % batch_size == 0) || (idx + 1 == total) {
-    //             for id in req_ids.iter() {
-    //                 let resp = self._recv(*id)?;
-    //                 let resp: Response<P::Response> = serde_json::from_str(&resp)?;
-    //                 results.push(resp);
-    //             }
-    //             req_ids.clear();
-    //         }
-    //     }
-    //     Ok(results)
-    // }
+    pub fn query<P: ApiQuery>(&self, param: P) -> ApiResult<P::Response> {
+        self.query_until(param, None)
+    }
 
-    pub fn query<P: ApiQuery>(&mut self, param: P) -> ApiResult<P::Response> {
-        let param = self.wrap(P::TYPE, param);
-        if self.stream.is_none() {
-            self.get_stream()?;
-            if let Some(signed) = self.auth.as_ref().map(|auth| auth.sign(self.recv_window)) {
-                self.query(signed)?;
-                debug!("logon ok");
-            }
+    /// 超时后返回 [`ClientError::Timeout`], 之后到达的响应会被丢弃
+    pub fn query_with_timeout<P: ApiQuery>(
+        &self,
+        param: P,
+        timeout: Duration,
+    ) -> ApiResult<P::Response> {
+        self.query_until(param, Some(Instant::now() + timeout))
+    }
+
+    fn query_until<P: ApiQuery>(
+        &self,
+        param: P,
+        deadline: Option<Instant>,
+    ) -> ApiResult<P::Response> {
+        if P::TYPE != QueryType::None
+            && self.clock.need_refresh()
+            && let Err(e) = self.sync_clock()
+        {
+            warn!("failed to sync clock {e}");
         }
-        match self._send(P::METHOD, &param) {
-            Ok(req_id) => match self._recv(req_id) {
-                Ok(resp) => serde_json::from_str(&resp).map_err(|e| e.into()),
-                Err(ClientError::WsError(e)) => {
-                    warn!("error while recv response {e}");
-                    self.stream = None;
-                    if let Some(signed) = self.auth.as_ref().map(|auth| auth.sign(self.recv_window))
-                    {
-                        self.query(signed)?;
-                        debug!("logon ok");
-                    }
-                    self.query_without_retry(&param)
-                }
-                Err(e) => {
-                    warn!("error while recv response {e}");
-                    // self.stream = None;
-                    // if let Some(signed) = self.auth.as_ref().map(|auth| auth.sign(self.recv_window))
-                    // {
-                    //     self.query(signed)?;
-                    //     info!("logon ok");
-                    // }
-                    self.query_without_retry(&param)
-                }
-            },
-            Err(e) => {
-                warn!("error while send message {e}");
-                self.stream = None;
-                if let Some(signed) = self.auth.as_ref().map(|auth| auth.sign(self.recv_window)) {
-                    self.query(signed)?;
-                    debug!("logon ok");
-                }
-                self.query_without_retry(&param)
+        let param = self.wrap(P::TYPE, param)?;
+        match self.query_without_retry(&param, deadline) {
+            // 请求已经发出时下单和撤单的执行状态未知, 不能重发
+            Err((ClientError::WsError(e), sent)) if !sent || !P::IS_ORDER => {
+                warn!("error while query {e}");
+                self.query_without_retry(&param, deadline)
+                    .map_err(|(e, _)| e)
             }
+            resp => resp.map_err(|(e, _)| e),
         }
     }
 
+    /// 失败时同时返回请求是否已经发出
     fn query_without_retry<P: ApiQuery>(
-        &mut self,
+        &self,
         param: &ParamWrapper<P>,
-    ) -> Result<Response<<P as ApiQuery>::Response>, ClientError> {
-        self.get_stream()?;
-        let req_id = self._send(P::METHOD, param)?;
-        let resp = self._recv(req_id)?;
-        serde_json::from_str(&resp).map_err(|e| e.into())
+        deadline: Option<Instant>,
+    ) -> Result<Response<<P as ApiQuery>::Response>, (ClientError, bool)> {
+        let conn = self.get_conn(deadline).map_err(|e| (e, false))?;
+        let permit = match &self.limiter {
+            Some(limiter) => Some(limiter.acquire_for(&param.other).map_err(|e| (e, false))?),
+            None => None,
+        };
+        let mut sent = false;
+        let resp = conn
+            .send(self.next_id(), P::METHOD, param, self.return_rate_limits())
+            .and_then(|(id, rx)| {
+                sent = true;
+                conn.recv(id, rx, deadline)
+            })
+            .and_then(|resp| serde_json::from_str(&resp).map_err(|e| e.into()));
+        if let (Some(limiter), Some(permit)) = (&self.limiter, permit) {
+            limiter.observe_for(permit, &resp);
+        }
+        self.clock.observe(&resp);
+        resp.map_err(|e| (e, sent))
+    }
+
+    fn return_rate_limits(&self) -> Option<bool> {
+        self.limiter.as_ref().map(|_| true)
     }
 
-    fn get_stream(&mut self) -> Result<&mut StringCodec<SyncStream>, WsError> {
-        let create_new_stream = self.stream.is_none();
-        if create_new_stream {
-            tracing::debug!("rebuild stream ...");
-            self.stream = Some((self.conn_fn)()?);
+    fn next_id(&self) -> u64 {
+        self.req_id.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    /// 获取当前连接, 连接断开时重建连接并重新登录
+    ///
+    /// 只在检查和替换连接时持有 `conn` 锁, 重建连接由 `conn_fn` 的锁串行化,
+    /// 重建连接, 登录和重新订阅最多等到 `deadline`, 未指定时等待 [`RECONNECT_TIMEOUT`]
+    fn get_conn(&self, deadline: Option<Instant>) -> Result<Arc<Connection>, ClientError> {
+        if let Some(c) = self.alive_conn() {
+            return Ok(c);
+        }
+        let mut conn_fn = self.conn_fn.lock().unwrap();
+        // 等锁期间其他线程可能已经重建了连接
+        if let Some(c) = self.alive_conn() {
+            return Ok(c);
         }
-        Ok(self.stream.as_mut().unwrap())
+        tracing::debug!("rebuild stream ...");
+        self.conn.lock().unwrap().take();
+        let deadline = deadline.unwrap_or_else(|| Instant::now() + RECONNECT_TIMEOUT);
+        let stream = conn_fn(deadline).map_err(|e| match Instant::now() >= deadline {
+            true => ClientError::Timeout,
+            false => e.into(),
+        })?;
+        let new_conn = Arc::new(Connection::new(stream, self.events.clone())?);
+        // 逐个请求签名时不需要登录
+        if let Some(auth) = self.auth.as_ref().filter(|_| self.signer.is_none()) {
+            let signed = auth.sign_at(self.recv_window, self.clock.now())?;
+            let permit = match &self.limiter {
+                Some(limiter) => Some(limiter.acquire_for(&signed)?),
+                None => None,
+            };
+            let resp = new_conn
+                .send(
+                    self.next_id(),
+                    SignedLogon::METHOD,
+                    &signed,
+                    self.return_rate_limits(),
+                )
+                .and_then(|(id, rx)| new_conn.recv(id, rx, Some(deadline)));
+            if let (Some(limiter), Some(permit)) = (&self.limiter, permit) {
+                limiter.release(permit);
+            }
+            resp?;
+            debug!("logon ok");
+        }
+        if self.user_stream.load(Ordering::Relaxed) {
+            let resp = match self.signer {
+                Some(_) => self.send_on(&new_conn, SubscribeUserStreamSignature, deadline),
+                None => self.send_on(&new_conn, SubscribeUserStream, deadline),
+            };
+            match resp {
+                Ok(_) => debug!("user data stream resubscribed"),
+                Err(e) => warn!("failed to resubscribe user data stream {e}"),
+            }
+        }
+        *self.conn.lock().unwrap() = Some(new_conn.clone());
+        Ok(new_conn)
     }
 
-    fn _send<T: Serialize>(
-        &mut self,
+    fn alive_conn(&self) -> Option<Arc<Connection>> {
+        self.conn
+            .lock()
+            .unwrap()
+            .as_ref()
+            .filter(|c| c.is_alive())
+            .cloned()
+    }
+
+    /// 在指定连接上发送请求并等待响应, 用于重建连接时恢复订阅
+    fn send_on<P: ApiQuery>(
+        &self,
+        conn: &Connection,
+        param: P,
+        deadline: Instant,
+    ) -> Result<String, ClientError> {
+        let param = self.wrap(P::TYPE, param)?;
+        let permit = match &self.limiter {
+            Some(limiter) => Some(limiter.acquire_for(&param.other)?),
+            None => None,
+        };
+        let resp = conn
+            .send(self.next_id(), P::METHOD, &param, self.return_rate_limits())
+            .and_then(|(id, rx)| conn.recv(id, rx, Some(deadline)));
+        if let (Some(limiter), Some(permit)) = (&self.limiter, permit) {
+            limiter.release(permit);
+        }
+        resp
+    }
+}
+
+/// 单条 websocket 连接, 由后台线程读取响应
+struct Connection {
+    writer: Arc<Mutex<StringSend<SyncStreamWrite>>>,
+    waiters: Arc<Waiters<Reply>>,
+    ctrl: Arc<ReadCtrl>,
+}
+
+impl Connection {
+    fn new(stream: SyncStream, events: Events) -> Result<Self, WsError> {
+        let ctrl = Arc::new(ReadCtrl::default());
+        let (reader, writer) = split_stream(stream, ctrl.clone())?;
+        let writer = Arc::new(Mutex::new(writer));
+        let waiters = Arc::new(Waiters::default());
+        let (writer_c, waiters_c) = (writer.clone(), waiters.clone());
+        std::thread::Builder::new()
+            .name("ws-api-reader".into())
+            .spawn(move || Self::read_loop(reader, writer_c, waiters_c, events))?;
+        Ok(Self {
+            writer,
+            waiters,
+            ctrl,
+        })
+    }
+
+    fn is_alive(&self) -> bool {
+        !self.waiters.is_closed()
+    }
+
+    fn send<T: Serialize>(
+        &self,
+        id: u64,
         method: &'static str,
         params: &T,
-    ) -> Result<u64, ClientError> {
-        self.req_id += 1;
-        let id = self.req_id;
+        return_rate_limits: Option<bool>,
+    ) -> Result<(u64, mpsc::Receiver<Result<String, ClientError>>), ClientError> {
         let req = Request {
             id,
             method,
             params,
-            return_rate_limits: None,
+            return_rate_limits,
         };
         let req = serde_json::to_string(&req)?;
         tracing::debug!("{req}");
-        self.get_stream()?.send(&req)?;
-        Ok(self.req_id)
+        let (tx, rx) = mpsc::channel();
+        self.waiters.register(id, tx)?;
+        if let Err(e) = self.writer.lock().unwrap().send(&req) {
+            // 发送失败说明连接已不可用, 关闭后由下一次请求重建连接
+            for (_, tx) in self.waiters.close() {
+                tx.send(Err(connection_closed(&e.to_string()))).ok();
+            }
+            return Err(e.into());
+        }
+        Ok((id, rx))
+    }
+
+    fn recv(
+        &self,
+        id: u64,
+        rx: mpsc::Receiver<Result<String, ClientError>>,
+        deadline: Option<Instant>,
+    ) -> Result<String, ClientError> {
+        let resp = match deadline {
+            Some(deadline) => rx
+                .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+                .map_err(|e| match e {
+                    mpsc::RecvTimeoutError::Timeout => ClientError::Timeout,
+                    mpsc::RecvTimeoutError::Disconnected => connection_closed("connection closed"),
+                }),
+            None => rx
+                .recv()
+                .map_err(|_| connection_closed("connection closed")),
+        };
+        // 移除等待者, 超时后到达的响应由读取线程直接丢弃
+        self.waiters.take(id);
+        resp?
     }
 
-    fn _recv(&mut self, id: u64) -> Result<String, ClientError> {
-        #[derive(Deserialize)]
-        struct CheckResp {
-            #[serde(default)]
-            id: u64,
-            status: i64,
-        }
-        if self.messages.contains_key(&id) {
-            Ok(self.messages.remove(&id).unwrap())
-        } else {
-            loop {
-                let msg = self.get_stream()?.receive()?;
-                let code = msg.code;
-                let data = msg.data.to_string();
-                match code {
-                    OpCode::Ping => {
-                        self.get_stream()?.pong(&data)?;
-                        tracing::debug!("got server ping, pong back ...");
+    fn read_loop(
+        mut reader: StringRecv<PollRead>,
+        writer: Arc<Mutex<StringSend<SyncStreamWrite>>>,
+        waiters: Arc<Waiters<Reply>>,
+        events: Events,
+    ) {
+        let reason = loop {
+            let msg = match reader.receive() {
+                Ok(msg) => msg,
+                Err(e) => break e.to_string(),
+            };
+            match msg.code {
+                OpCode::Ping => {
+                    if let Err(e) = writer.lock().unwrap().pong(&msg.data) {
+                        break e.to_string();
                     }
-                    OpCode::Text => {
-                        let resp: CheckResp = serde_json::from_str(&msg.data)?;
-                        // check error response first
-                        if resp.id == 0 || resp.status != 200 {
-                            let err: ErrResponse = serde_json::from_str(&msg.data)?;
-                            break Err(ClientError::ApiError(err));
-                        } else if resp.id != id {
-                            self.messages.insert(resp.id, data);
-                        } else {
-                            // avoid to print too much when response is large
-                            if msg.data.len() <= 8192 {
-                                tracing::debug!("{}", msg.data);
-                            } else {
-                                tracing::debug!(
-                                    "truncated: {}",
-                                    msg.data.chars().take(8192).collect::<String>()
-                                );
-                            }
-                            break Ok(msg.data.to_string());
+                    tracing::debug!("got server ping, pong back ...");
+                }
+                OpCode::Text => match decode_frame(&msg.data) {
+                    Ok(Frame::Response(id, resp)) => match waiters.take(id) {
+                        Some(tx) => {
+                            tx.send(resp).ok();
+                        }
+                        None => warn!("drop response without waiter {id}"),
+                    },
+                    Ok(Frame::Unsolicited(msg)) => events.dispatch(msg),
+                    Err(e) => warn!("invalid response {e}: {}", msg.data),
+                },
+                OpCode::Close => break "connection closed by server".to_string(),
+                c => {
+                    tracing::error!("unexpected frame type {c:?}");
+                }
+            }
+        };
+        debug!("reader exit: {reason}");
+        for (_, tx) in waiters.close() {
+            tx.send(Err(connection_closed(&reason))).ok();
+        }
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        self.ctrl.closed.store(true, Ordering::Relaxed);
+    }
+}
+
+/// 完成 websocket 握手, `wss` 使用 rustls, `ws` 使用明文连接
+fn handshake(url: &url::Url, stream: TcpStream) -> Result<SyncStream, WsError> {
+    let stream = match url.scheme() {
+        "ws" => SyncStream::Raw(stream),
+        _ => SyncStream::Rustls(wrap_rustls(stream, url.host_str().unwrap(), vec![])?),
+    };
+    ClientBuilder::new().with_stream(
+        url.as_str().parse().unwrap(),
+        stream,
+        |key, resp, stream| {
+            standard_handshake_resp_check(key.as_bytes(), &resp)?;
+            Ok(stream)
+        },
+    )
+}
+
+/// 距离截止时间的剩余时间, 已经超过时返回超时错误
+fn remaining(deadline: Instant) -> std::io::Result<Duration> {
+    deadline
+        .checked_duration_since(Instant::now())
+        .filter(|d| !d.is_zero())
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::TimedOut, "reconnect timeout"))
+}
+
+/// 在截止时间前建立 tcp 连接, 依次尝试解析到的地址
+fn connect_before(url: &url::Url, deadline: Instant) -> std::io::Result<TcpStream> {
+    let mut last_err = None;
+    for addr in url.socket_addrs(|| Some(443))? {
+        match TcpStream::connect_timeout(&addr, remaining(deadline)?) {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_err = Some(e),
+        }
+    }
+    Err(last_err.unwrap_or_else(|| {
+        std::io::Error::new(std::io::ErrorKind::NotFound, "no address resolved")
+    }))
+}
+
+/// 完成握手的连接拆分为读写两部分, 读取部分包装为 [`PollRead`]
+fn split_stream(
+    stream: SyncStream,
+    ctrl: Arc<ReadCtrl>,
+) -> Result<(StringRecv<PollRead>, StringSend<SyncStreamWrite>), WsError> {
+    let sock = match &stream {
+        SyncStream::Raw(s) => s.try_clone()?,
+        SyncStream::Rustls(s) => s.sock.try_clone()?,
+    };
+    sock.set_read_timeout(Some(POLL_INTERVAL))?;
+    let (read, write) = stream.split();
+    let reader = StringRecv::new(
+        PollRead {
+            inner: read,
+            sock,
+            ctrl,
+            buffered: false,
+            boundary: Default::default(),
+        },
+        FrameReadState::with_config(FrameConfig::default()),
+        true,
+    );
+    let writer = StringSend::new(write, FrameWriteState::with_config(FrameConfig::default()));
+    Ok((reader, writer))
+}
+
+/// 控制 [`PollRead`] 的读取: 连接关闭或超过截止时间后返回错误
+#[derive(Default)]
+struct ReadCtrl {
+    closed: AtomicBool,
+    deadline: Mutex<Option<Instant>>,
+}
+
+impl ReadCtrl {
+    fn is_expired(&self) -> bool {
+        self.deadline
+            .lock()
+            .unwrap()
+            .is_some_and(|d| d <= Instant::now())
+    }
+}
+
+/// 带超时控制的读半边
+///
+/// rustls 连接拆分后读写共用一把锁, 阻塞读取会一直占用这把锁导致无法发送请求,
+/// 所以先在不持锁的情况下 peek 底层 socket, 确认有数据后再读取.
+/// 超过截止时间只在已交给 codec 的数据都是完整帧时返回, 读到一半的帧继续等待,
+/// 避免 codec 的状态被打断
+struct PollRead {
+    inner: SyncStreamRead,
+    sock: TcpStream,
+    ctrl: Arc<ReadCtrl>,
+    /// 上次读取填满了缓冲区, tls 层可能还有未读的数据
+    buffered: bool,
+    boundary: FrameBoundary,
+}
+
+impl Read for PollRead {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        fn is_timeout(e: &std::io::Error) -> bool {
+            matches!(
+                e.kind(),
+                std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+            )
+        }
+        loop {
+            if self.ctrl.closed.load(Ordering::Relaxed) {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::ConnectionAborted,
+                    "client closed",
+                ));
+            }
+            if !self.buffered {
+                match self.sock.peek(&mut [0]) {
+                    Ok(_) => {}
+                    Err(e) if is_timeout(&e) => {
+                        if self.boundary.is_complete() && self.ctrl.is_expired() {
+                            return Err(std::io::ErrorKind::TimedOut.into());
                         }
-                    }
-                    c => {
-                        tracing::error!("unexpected frame type {c:?}");
                         continue;
                     }
+                    Err(e) => return Err(e),
+                }
+            }
+            match self.inner.read(buf) {
+                Ok(n) => {
+                    self.buffered = n > 0 && n == buf.len();
+                    self.boundary.feed(&buf[..n]);
+                    return Ok(n);
+                }
+                Err(e) if is_timeout(&e) => {
+                    self.buffered = false;
                 }
+                Err(e) => return Err(e),
             }
         }
     }
 }
 
+/// 跟踪读到的字节流中 websocket 帧的边界
+#[derive(Default)]
+struct FrameBoundary {
+    /// 当前帧已读到的帧头
+    header: Vec<u8>,
+    /// 当前帧剩余的负载长度
+    remaining: u64,
+}
+
+impl FrameBoundary {
+    fn is_complete(&self) -> bool {
+        self.header.is_empty() && self.remaining == 0
+    }
+
+    fn feed(&mut self, mut data: &[u8]) {
+        while !data.is_empty() {
+            if self.remaining > 0 {
+                let n = data.len().min(self.remaining as usize);
+                self.remaining -= n as u64;
+                data = &data[n..];
+                continue;
+            }
+            self.header.push(data[0]);
+            data = &data[1..];
+            if let Some(len) = self.payload_len() {
+                self.remaining = len;
+                self.header.clear();
+            }
+        }
+    }
+
+    /// 帧头完整时返回负载长度
+    fn payload_len(&self) -> Option<u64> {
+        let second = *self.header.get(1)?;
+        let (ext, len) = match second & 0x7f {
+            126 => (2, None),
+            127 => (8, None),
+            len => (0, Some(len as u64)),
+        };
+        let mask = if second & 0x80 != 0 { 4 } else { 0 };
+        if self.header.len() < 2 + ext + mask {
+            return None;
+        }
+        let len = len.unwrap_or_else(|| {
+            self.header[2..2 + ext]
+                .iter()
+                .fold(0, |len, b| (len << 8) | *b as u64)
+        });
+        Some(len)
+    }
+}
+
+/// 等待响应的请求, 连接断开后置为 `None`, 不再接受新的请求
+struct Waiters<T>(Mutex<Option<HashMap<u64, T>>>);
+
+impl<T> Default for Waiters<T> {
+    fn default() -> Self {
+        Self(Mutex::new(Some(HashMap::new())))
+    }
+}
+
+impl<T> Waiters<T> {
+    fn register(&self, id: u64, reply: T) -> Result<(), ClientError> {
+        match self.0.lock().unwrap().as_mut() {
+            Some(waiters) => {
+                waiters.insert(id, reply);
+                Ok(())
+            }
+            None => Err(connection_closed("connection closed")),
+        }
+    }
+
+    fn take(&self, id: u64) -> Option<T> {
+        self.0.lock().unwrap().as_mut().and_then(|w| w.remove(&id))
+    }
+
+    fn is_closed(&self) -> bool {
+        self.0.lock().unwrap().is_none()
+    }
+
+    fn close(&self) -> HashMap<u64, T> {
+        self.0.lock().unwrap().take().unwrap_or_default()
+    }
+}
+
+fn connection_closed(reason: &str) -> ClientError {
+    ClientError::WsError(WsError::ConnectionFailed(reason.to_string()))
+}
+
+/// 收到的文本帧
+enum Frame {
+    /// 请求 id 及对应的结果
+    Response(u64, Result<String, ClientError>),
+    /// 不属于任何请求的消息
+    Unsolicited(ServerMessage),
+}
+
+/// 解析文本帧: 带 id 的响应或错误交给对应的请求, 其余为推送消息
+fn decode_frame(data: &str) -> Result<Frame, serde_json::Error> {
+    #[derive(Deserialize)]
+    struct CheckResp {
+        #[serde(default, deserialize_with = "null_as_zero")]
+        id: u64,
+        status: Option<i64>,
+        #[serde(default)]
+        event: Option<serde::de::IgnoredAny>,
+    }
+    let resp: CheckResp = serde_json::from_str(data)?;
+    let status = match resp.status {
+        Some(status) if resp.id != 0 => status,
+        Some(200) | None => {
+            let msg = match resp.event {
+                Some(_) => ServerMessage::Event(serde_json::from_str(data)?),
+                None => ServerMessage::Unknown(serde_json::from_str(data)?),
+            };
+            return Ok(Frame::Unsolicited(msg));
+        }
+        Some(_) => {
+            return Ok(Frame::Unsolicited(ServerMessage::Error(
+                serde_json::from_str(data)?,
+            )));
+        }
+    };
+    if status != 200 {
+        let err: ErrResponse = serde_json::from_str(data)?;
+        return Ok(Frame::Response(resp.id, Err(ClientError::ApiError(err))));
+    }
+    // avoid to print too much when response is large
+    if data.len() <= 1024 {
+        tracing::debug!("{}", data);
+    } else {
+        tracing::debug!("truncated: {}", data.chars().take(1024).collect::<String>());
+    }
+    Ok(Frame::Response(resp.id, Ok(data.to_string())))
+}
+
+fn null_as_zero<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    Ok(Option::<u64>::deserialize(deserializer)?.unwrap_or_default())
+}
+
 pub struct Client {
     req_id: u64,
-    reader: StringRecv<SyncStreamRead>,
+    reader: StringRecv<PollRead>,
     writer: StringSend<SyncStreamWrite>,
+    ctrl: Arc<ReadCtrl>,
     api_key: String,
     recv_window: i64,
-    messages: BTreeMap<u64, String>,
+    /// 先于等待者到达的响应
+    messages: BTreeMap<u64, Result<String, ClientError>>,
+    /// 已超时的请求, 之后收到的响应直接丢弃, 最多保留 [`MAX_EXPIRED`] 个
+    expired: BTreeSet<u64>,
+    limiter: Option<Arc<RateLimiter>>,
+    clock: Arc<ClockSync>,
+    signer: Option<RequestSigner>,
+    events: Events,
 }
 
 impl Client {
@@ -370,7 +1031,7 @@ impl Client {
                 sproxy::create_conn(
                     &config,
                     url.host_str().unwrap().into(),
-                    url.port().unwrap_or(443),
+                    url.port_or_known_default().unwrap_or(443),
                 )?
                 .0
             }
@@ -381,20 +1042,85 @@ impl Client {
             stream.local_addr(),
             stream.peer_addr()
         );
-        let stream = wrap_rustls(stream, url.host_str().unwrap(), vec![])?;
-        let stream = SyncStream::Rustls(stream);
-        let (reader, writer) = ClientBuilder::new()
-            .with_stream(url.as_str().parse().unwrap(), stream, StringCodec::check_fn)?
-            .split();
+        let stream = handshake(&url, stream)?;
+        let ctrl = Arc::new(ReadCtrl::default());
+        let (reader, writer) = split_stream(stream, ctrl.clone())?;
         Ok(Client {
             req_id: 0,
             reader,
             writer,
+            ctrl,
             recv_window,
             api_key: Default::default(),
             messages: Default::default(),
+            expired: Default::default(),
+            limiter: None,
+            clock: Default::default(),
+            signer: None,
+            events: Default::default(),
         })
     }
+
+    /// 使用校准后的时间签名, 多个客户端可以共享同一个时钟
+    pub fn with_clock(mut self, clock: Arc<ClockSync>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn clock(&self) -> &Arc<ClockSync> {
+        &self.clock
+    }
+
+    /// 立即校准时间, 返回新的偏移(ms)
+    pub fn sync_clock(&mut self) -> Result<i64, ClientError> {
+        let clock = self.clock.clone();
+        clock.sync(|| self.query(QServerTime).map(|resp| resp.result.time))
+    }
+
+    /// 签名请求前按需校准时间
+    fn refresh_clock(&mut self, ty: QueryType) {
+        if ty != QueryType::None
+            && self.clock.need_refresh()
+            && let Err(e) = self.sync_clock()
+        {
+            warn!("failed to sync clock {e}");
+        }
+    }
+
+    /// 发送请求前检查限频, 多个客户端可以共享同一个限频器
+    pub fn with_rate_limiter(mut self, limiter: Arc<RateLimiter>) -> Self {
+        self.limiter = Some(limiter);
+        self
+    }
+
+    /// 逐个请求签名, 不依赖 session.logon
+    pub fn with_signer(mut self, signer: RequestSigner) -> Self {
+        self.signer = Some(signer);
+        self
+    }
+
+    /// 处理不属于任何请求的消息, 在等待响应时调用
+    pub fn with_event_handler(
+        self,
+        handler: impl Fn(ServerMessage) + Send + Sync + 'static,
+    ) -> Self {
+        self.events.set(Arc::new(handler));
+        self
+    }
+
+    /// 订阅用户数据, 需要先登录或设置签名器.
+    /// 事件只在等待响应时读取, 没有请求时可以用 [`Client::poll_events`] 读取
+    pub fn subscribe_user_stream(&mut self) -> ApiResult<UserStreamSubscription> {
+        match self.signer {
+            Some(_) => self.query(SubscribeUserStreamSignature),
+            None => self.query(SubscribeUserStream),
+        }
+    }
+
+    /// 取消当前连接上的所有用户数据订阅
+    pub fn unsubscribe_user_stream(&mut self) -> ApiResult<EmptyResponse> {
+        self.query(UnsubscribeUserStream::default())
+    }
 }
 
 pub type ApiResult<T> = Result<Response<T>, ClientError>;
@@ -410,6 +1136,18 @@ pub trait ApiQuery: Sized + Serialize {
     type Response: DeserializeOwned;
     const METHOD: &'static str;
     const TYPE: QueryType;
+    /// 是否计入下单频率限制(ORDERS)
+    const IS_ORDER: bool = false;
+
+    /// 请求权重(REQUEST_WEIGHT)
+    fn weight(&self) -> u32 {
+        1
+    }
+
+    /// 合约接口的请求权重, 现货和合约共用的请求在两边权重不同时覆盖
+    fn future_weight(&self) -> u32 {
+        self.weight()
+    }
 }
 
 macro_rules! empty_serde {
@@ -428,12 +1166,23 @@ macro_rules! empty_serde {
 }
 /// 账户请求
 pub mod account;
+/// 异步(tokio)客户端
+#[cfg(feature = "async")]
+pub mod async_client;
+/// 服务器时间校准
+pub mod clock;
 /// 常用API
 pub mod common;
+/// 错误码
+pub mod error;
 /// 行情接口
 pub mod market;
+/// 客户端限频
+pub mod rate_limit;
 /// 身份认证
 pub mod session;
+/// 请求签名
+pub mod sign;
 /// 交易接口
 pub mod trade;
 /// 用户数据流
@@ -457,51 +1206,159 @@ impl Client {
         batch_size: usize,
         params: Vec<P>,
     ) -> Result<Vec<Response<P::Response>>, ClientError> {
-        let mut req_ids = vec![];
-        let mut results = vec![];
-        let total = params.len();
-        for (idx, p) in params.into_iter().enumerate() {
-            let param = self.wrap(P::TYPE, p);
-            req_ids.push(self._send(P::METHOD, param)?);
-            if (idx != 0 && idx % batch_size == 0) || (idx + 1 == total) {
-                for id in req_ids.iter() {
-                    let resp = self._recv(*id)?;
-                    let resp: Response<P::Response> = serde_json::from_str(&resp)?;
-                    results.push(resp);
+        let batch_size = batch_size.max(1);
+        let mut results = Vec::with_capacity(params.len());
+        let mut pending = Vec::with_capacity(batch_size);
+        let mut params = params.into_iter().peekable();
+        while let Some(p) = params.next() {
+            if let Err(e) = self.send_in_batch(p, &mut pending) {
+                for (id, permit) in pending {
+                    self.abandon(id, permit);
+                }
+                return Err(e);
+            }
+            if pending.len() < batch_size && params.peek().is_some() {
+                continue;
+            }
+            let mut error = None;
+            for (id, permit) in std::mem::take(&mut pending) {
+                if error.is_some() {
+                    self.abandon(id, permit);
+                    continue;
+                }
+                let resp = self._recv(id, None).and_then(|resp| {
+                    serde_json::from_str::<Response<P::Response>>(&resp).map_err(|e| e.into())
+                });
+                self.observe(permit, &resp);
+                match resp {
+                    Ok(resp) => results.push(resp),
+                    Err(e) => error = Some(e),
                 }
-                req_ids.clear();
+            }
+            if let Some(e) = error {
+                return Err(e);
             }
         }
         Ok(results)
     }
 
+    /// 发送批量请求中的一个, 成功后加入 `pending`
+    fn send_in_batch<P: ApiQuery>(
+        &mut self,
+        param: P,
+        pending: &mut Vec<(u64, Option<Permit>)>,
+    ) -> Result<(), ClientError> {
+        self.refresh_clock(P::TYPE);
+        let param = self.wrap(P::TYPE, param)?;
+        let permit = self.acquire(&param.other)?;
+        match self._send(P::METHOD, param) {
+            Ok(id) => {
+                pending.push((id, permit));
+                Ok(())
+            }
+            Err(e) => {
+                let resp = Err(e);
+                self.observe::<()>(permit, &resp);
+                resp.map(|_| ())
+            }
+        }
+    }
+
+    /// 不再读取 `id` 的响应, 释放占用的限频额度
+    fn abandon(&mut self, id: u64, permit: Option<Permit>) {
+        if let (Some(limiter), Some(permit)) = (&self.limiter, permit) {
+            limiter.release(permit);
+        }
+        if self.messages.remove(&id).is_none() {
+            self.expire(id);
+        }
+    }
+
     pub fn query<P: ApiQuery>(&mut self, param: P) -> ApiResult<P::Response> {
-        let param = self.wrap(P::TYPE, param);
-        let req_id = self._send(P::METHOD, param)?;
-        let resp = self._recv(req_id)?;
-        serde_json::from_str(&resp).map_err(|e| e.into())
+        self.refresh_clock(P::TYPE);
+        let param = self.wrap(P::TYPE, param)?;
+        let permit = self.acquire(&param.other)?;
+        let resp = self
+            ._send(P::METHOD, param)
+            .and_then(|req_id| self._recv(req_id, None))
+            .and_then(|resp| serde_json::from_str(&resp).map_err(|e| e.into()));
+        self.observe(permit, &resp);
+        resp
     }
 
-    fn wrap<P>(&mut self, ty: QueryType, param: P) -> ParamWrapper<P> {
-        match ty {
+    /// 超时后返回 [`ClientError::Timeout`], 之后到达的响应会被丢弃
+    pub fn query_with_timeout<P: ApiQuery>(
+        &mut self,
+        param: P,
+        timeout: Duration,
+    ) -> ApiResult<P::Response> {
+        self.refresh_clock(P::TYPE);
+        let param = self.wrap(P::TYPE, param)?;
+        let permit = self.acquire(&param.other)?;
+        let resp = self._send(P::METHOD, param).and_then(|req_id| {
+            match self._recv(req_id, Some(Instant::now() + timeout)) {
+                Err(ClientError::Timeout) => {
+                    self.expire(req_id);
+                    Err(ClientError::Timeout)
+                }
+                resp => resp.and_then(|resp| serde_json::from_str(&resp).map_err(|e| e.into())),
+            }
+        });
+        self.observe(permit, &resp);
+        resp
+    }
+
+    /// 记录超时的请求, id 递增, 超过上限时丢弃最早的记录
+    fn expire(&mut self, id: u64) {
+        self.expired.insert(id);
+        while self.expired.len() > MAX_EXPIRED {
+            self.expired.pop_first();
+        }
+    }
+
+    fn acquire<P: ApiQuery>(&self, param: &P) -> Result<Option<Permit>, ClientError> {
+        match &self.limiter {
+            Some(limiter) => limiter.acquire_for(param).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn observe<T>(&self, permit: Option<Permit>, resp: &ApiResult<T>) {
+        if let (Some(limiter), Some(permit)) = (&self.limiter, permit) {
+            limiter.observe_for(permit, resp);
+        }
+        self.clock.observe(resp);
+    }
+
+    fn wrap<P: Serialize>(&self, ty: QueryType, param: P) -> Result<ParamWrapper<P>, ClientError> {
+        let param = match ty {
             QueryType::None => ParamWrapper {
                 recv_window: None,
                 api_key: None,
                 timestamp: 0,
+                signature: None,
                 other: param,
             },
             QueryType::Authorized => ParamWrapper {
                 recv_window: Some(self.recv_window),
                 api_key: Some(self.api_key.clone()),
-                timestamp: millis_ts(),
+                timestamp: self.clock.now(),
+                signature: None,
                 other: param,
             },
             QueryType::AuthWithoutApiKey => ParamWrapper {
                 recv_window: Some(self.recv_window),
                 api_key: None,
-                timestamp: millis_ts(),
+                timestamp: self.clock.now(),
+                signature: None,
                 other: param,
             },
+        };
+        match (&self.signer, ty) {
+            (Some(signer), QueryType::Authorized | QueryType::AuthWithoutApiKey) => {
+                Ok(param.sign(signer)?)
+            }
+            _ => Ok(param),
         }
     }
 
@@ -512,7 +1369,7 @@ impl Client {
             id,
             method,
             params,
-            return_rate_limits: None,
+            return_rate_limits: self.limiter.as_ref().map(|_| true),
         };
         let req = serde_json::to_string(&req)?;
         tracing::debug!("{req}");
@@ -520,48 +1377,84 @@ impl Client {
         Ok(self.req_id)
     }
 
-    fn _recv(&mut self, id: u64) -> Result<String, ClientError> {
-        #[derive(Deserialize)]
-        struct CheckResp {
-            #[serde(default)]
-            id: u64,
-            status: i64,
-        }
-        if self.messages.contains_key(&id) {
-            Ok(self.messages.remove(&id).unwrap())
-        } else {
-            loop {
-                let msg = self.reader.receive()?;
-                match msg.code {
-                    OpCode::Ping => {
-                        self.writer.pong(&msg.data)?;
-                        tracing::debug!("got server ping, pong back ...");
+    /// 在 `timeout` 内读取推送事件并交给处理函数, 期间收到的响应留给之后的请求
+    pub fn poll_events(&mut self, timeout: Duration) -> Result<(), ClientError> {
+        *self.ctrl.deadline.lock().unwrap() = Some(Instant::now() + timeout);
+        let ret = loop {
+            if self.ctrl.is_expired() {
+                break Ok(());
+            }
+            let msg = match self.reader.receive() {
+                Ok(msg) => msg,
+                Err(WsError::IOError(e)) if e.kind() == std::io::ErrorKind::TimedOut => {
+                    break Ok(());
+                }
+                Err(e) => break Err(e.into()),
+            };
+            match msg.code {
+                OpCode::Ping => {
+                    if let Err(e) = self.writer.pong(&msg.data) {
+                        break Err(e.into());
                     }
-                    OpCode::Text => {
-                        let resp: CheckResp = serde_json::from_str(&msg.data)?;
-                        // check error response first
-                        if resp.id == 0 || resp.status != 200 {
-                            let err: ErrResponse = serde_json::from_str(&msg.data)?;
-                            break Err(ClientError::ApiError(err));
-                        } else if resp.id != id {
-                            self.messages.insert(resp.id, msg.data.to_string());
-                        } else {
-                            // avoid to print too much when response is large
-                            if msg.data.len() <= 1024 {
-                                tracing::debug!("{}", msg.data);
-                            } else {
-                                tracing::debug!(
-                                    "truncated: {}",
-                                    msg.data.chars().take(1024).collect::<String>()
-                                );
-                            }
-                            break Ok(msg.data.to_string());
-                        }
+                }
+                OpCode::Text => match decode_frame(&msg.data) {
+                    Ok(Frame::Unsolicited(msg)) => self.events.dispatch(msg),
+                    Ok(Frame::Response(resp_id, _)) if self.expired.remove(&resp_id) => {}
+                    Ok(Frame::Response(resp_id, resp)) => {
+                        self.messages.insert(resp_id, resp);
                     }
-                    c => {
-                        tracing::error!("unexpected frame type {c:?}");
-                        continue;
+                    Err(e) => warn!("invalid response {e}: {}", msg.data),
+                },
+                c => tracing::error!("unexpected frame type {c:?}"),
+            }
+        };
+        *self.ctrl.deadline.lock().unwrap() = None;
+        ret
+    }
+
+    /// 等待 `id` 的响应, 其他请求的响应先缓存, 推送消息交给处理函数.
+    /// 超过 `deadline` 时返回 [`ClientError::Timeout`]
+    fn _recv(&mut self, id: u64, deadline: Option<Instant>) -> Result<String, ClientError> {
+        if let Some(resp) = self.messages.remove(&id) {
+            return resp;
+        }
+        *self.ctrl.deadline.lock().unwrap() = deadline;
+        let resp = self.recv_until(id);
+        *self.ctrl.deadline.lock().unwrap() = None;
+        match resp {
+            Err(ClientError::WsError(WsError::IOError(e)))
+                if e.kind() == std::io::ErrorKind::TimedOut =>
+            {
+                Err(ClientError::Timeout)
+            }
+            resp => resp,
+        }
+    }
+
+    fn recv_until(&mut self, id: u64) -> Result<String, ClientError> {
+        loop {
+            // 持续有数据时 PollRead 不会超时, 每收到一帧检查一次
+            if self.ctrl.is_expired() {
+                return Err(ClientError::Timeout);
+            }
+            let msg = self.reader.receive()?;
+            match msg.code {
+                OpCode::Ping => {
+                    self.writer.pong(&msg.data)?;
+                    tracing::debug!("got server ping, pong back ...");
+                }
+                OpCode::Text => match decode_frame(&msg.data)? {
+                    Frame::Unsolicited(msg) => self.events.dispatch(msg),
+                    Frame::Response(resp_id, _) if self.expired.remove(&resp_id) => {
+                        tracing::debug!("drop expired response {resp_id}");
+                    }
+                    Frame::Response(resp_id, resp) if resp_id == id => break resp,
+                    Frame::Response(resp_id, resp) => {
+                        self.messages.insert(resp_id, resp);
                     }
+                },
+                c => {
+                    tracing::error!("unexpected frame type {c:?}");
                 }
             }
         }
@@ -577,10 +1470,50 @@ pub struct ParamWrapper<T> {
     pub api_key: Option<String>,
     #[serde(skip_serializing_if = "is_zero")]
     pub timestamp: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
     #[serde(flatten)]
     pub other: T,
 }
 
+impl<T: Serialize> ParamWrapper<T> {
+    /// 除 `signature` 和空值外的参数, 按名称排序
+    pub fn params(&self) -> Result<Vec<(String, String)>, serde_json::Error> {
+        let serde_json::Value::Object(params) = serde_json::to_value(self)? else {
+            return Ok(vec![]);
+        };
+        let mut params: Vec<(String, String)> = params
+            .into_iter()
+            .filter(|(k, v)| k != "signature" && !v.is_null())
+            .map(|(k, v)| match v {
+                serde_json::Value::String(s) => (k, s),
+                v => (k, v.to_string()),
+            })
+            .collect();
+        params.sort();
+        Ok(params)
+    }
+
+    /// 签名内容: 除 `signature` 外的参数按名称排序后拼接为 `k1=v1&k2=v2`
+    pub fn payload(&self) -> Result<String, serde_json::Error> {
+        Ok(self
+            .params()?
+            .iter()
+            .map(|(k, v)| format!("{k}={v}"))
+            .collect::<Vec<_>>()
+            .join("&"))
+    }
+
+    /// 带上 api key 并签名
+    pub fn sign(mut self, signer: &RequestSigner) -> Result<Self, serde_json::Error> {
+        self.api_key = Some(signer.api_key.clone());
+        self.signature = None;
+        let payload = self.payload()?;
+        self.signature = Some(signer.signer.sign(payload.as_bytes()));
+        Ok(self)
+    }
+}
+
 fn is_zero(v: &i64) -> bool {
     *v == 0
 }
@@ -1,6 +1,8 @@
 use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
 
+use crate::rest::RestQuery;
+
 use super::{ApiQuery, QueryType, trade::PositionSide};
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -12,6 +14,14 @@ impl ApiQuery for QV2FutureAccountStatus {
 
     const METHOD: &'static str = "v2/account.status";
     const TYPE: QueryType = QueryType::AuthWithoutApiKey;
+
+    fn weight(&self) -> u32 {
+        5
+    }
+}
+
+impl RestQuery for QV2FutureAccountStatus {
+    const PATH: &'static str = "/fapi/v2/account";
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -111,6 +121,14 @@ impl ApiQuery for QAccountStatus {
     type Response = AccountStatus;
     const METHOD: &'static str = "account.status";
     const TYPE: QueryType = QueryType::AuthWithoutApiKey;
+
+    fn weight(&self) -> u32 {
+        20
+    }
+}
+
+impl RestQuery for QAccountStatus {
+    const PATH: &'static str = "/api/v3/account";
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -0,0 +1,405 @@
+use std::sync::{
+    Arc,
+    atomic::{AtomicU64, Ordering},
+};
+
+use serde::Serialize;
+use sproxy::ProxyConfig;
+use tokio::{
+    io::{ReadHalf, WriteHalf},
+    sync::oneshot,
+    task::JoinHandle,
+};
+use tracing::warn;
+use ws_tool::{
+    ClientBuilder,
+    codec::{AsyncStringCodec, AsyncStringRecv, AsyncStringSend},
+    connector::{async_tcp_connect, async_wrap_rustls},
+    errors::WsError,
+    frame::OpCode,
+    stream::AsyncStream,
+};
+
+use crate::millis_ts;
+
+use super::common::QServerTime;
+
+use super::{
+    ApiQuery, ApiResult, ClientError, ClockSync, EmptyResponse, Events, Frame, ParamWrapper,
+    QueryType, RateLimiter, Request, RequestSigner, Response, ServerMessage, Waiters,
+    connection_closed, decode_frame,
+    rate_limit::Permit,
+    user_stream::{
+        SubscribeUserStream, SubscribeUserStreamSignature, UnsubscribeUserStream,
+        UserStreamSubscription,
+    },
+};
+
+type Writer = Arc<tokio::sync::Mutex<AsyncStringSend<WriteHalf<AsyncStream>>>>;
+type Reply = oneshot::Sender<Result<String, ClientError>>;
+type Receiver = oneshot::Receiver<Result<String, ClientError>>;
+
+/// 请求结束或调用方放弃等待(future 被 drop)时移除对应的等待者
+struct WaitGuard<'a> {
+    id: u64,
+    waiters: &'a Waiters<Reply>,
+}
+
+impl Drop for WaitGuard<'_> {
+    fn drop(&mut self) {
+        self.waiters.take(self.id);
+    }
+}
+
+/// 基于 tokio 的 WebSocket API 客户端
+///
+/// 所有方法只需要 `&self`, 多个任务可以共享同一个连接并同时发起请求,
+/// 后台读取任务按 id 将响应分发给对应的请求
+pub struct AsyncClient {
+    req_id: AtomicU64,
+    writer: Writer,
+    waiters: Arc<Waiters<Reply>>,
+    api_key: String,
+    recv_window: i64,
+    reader: JoinHandle<()>,
+    limiter: Option<Arc<RateLimiter>>,
+    clock: Arc<ClockSync>,
+    signer: Option<RequestSigner>,
+    events: Events,
+}
+
+impl AsyncClient {
+    pub async fn conn(
+        url: &str,
+        proxy: Option<ProxyConfig>,
+        recv_window: i64,
+    ) -> Result<AsyncClient, WsError> {
+        let url = url::Url::parse(url).map_err(|e| WsError::InvalidUri(e.to_string()))?;
+        let stream = match proxy {
+            Some(config) => {
+                sproxy::async_create_conn(
+                    &config,
+                    url.host_str().unwrap().into(),
+                    url.port_or_known_default().unwrap_or(443),
+                )
+                .await?
+                .0
+            }
+            None => async_tcp_connect(&url.as_str().parse().unwrap()).await?,
+        };
+        tracing::debug!(
+            "socket info local {:?} remote {:?}",
+            stream.local_addr(),
+            stream.peer_addr()
+        );
+        let stream = match url.scheme() {
+            "ws" => AsyncStream::Raw(stream),
+            _ => AsyncStream::Rustls(
+                async_wrap_rustls(stream, url.host_str().unwrap(), vec![])
+                    .await?
+                    .into(),
+            ),
+        };
+        let (reader, writer) = ClientBuilder::new()
+            .async_with_stream(
+                url.as_str().parse().unwrap(),
+                stream,
+                AsyncStringCodec::check_fn,
+            )
+            .await?
+            .split();
+        let writer = Arc::new(tokio::sync::Mutex::new(writer));
+        let waiters = Arc::new(Waiters::default());
+        let events = Events::default();
+        let reader = tokio::spawn(Self::read_loop(
+            reader,
+            writer.clone(),
+            waiters.clone(),
+            events.clone(),
+        ));
+        Ok(AsyncClient {
+            req_id: AtomicU64::new(0),
+            writer,
+            waiters,
+            api_key: Default::default(),
+            recv_window,
+            reader,
+            limiter: None,
+            clock: Default::default(),
+            signer: None,
+            events,
+        })
+    }
+
+    /// 使用校准后的时间签名, 多个客户端可以共享同一个时钟
+    pub fn with_clock(mut self, clock: Arc<ClockSync>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn clock(&self) -> &Arc<ClockSync> {
+        &self.clock
+    }
+
+    /// 立即校准时间, 返回新的偏移(ms)
+    pub async fn sync_clock(&self) -> Result<i64, ClientError> {
+        let send_ts = millis_ts();
+        let server_time = self.query_unsynced(QServerTime).await?.result.time;
+        Ok(self.clock.record(send_ts, millis_ts(), server_time))
+    }
+
+    /// 签名请求前按需校准时间
+    async fn refresh_clock(&self, ty: QueryType) {
+        if ty != QueryType::None
+            && self.clock.need_refresh()
+            && let Err(e) = self.sync_clock().await
+        {
+            warn!("failed to sync clock {e}");
+        }
+    }
+
+    /// 发送请求前检查限频, 多个客户端可以共享同一个限频器
+    pub fn with_rate_limiter(mut self, limiter: Arc<RateLimiter>) -> Self {
+        self.limiter = Some(limiter);
+        self
+    }
+
+    /// 逐个请求签名, 不依赖 session.logon
+    pub fn with_signer(mut self, signer: RequestSigner) -> Self {
+        self.signer = Some(signer);
+        self
+    }
+
+    /// 处理不属于任何请求的消息, 在读取任务中调用
+    pub fn with_event_handler(
+        self,
+        handler: impl Fn(ServerMessage) + Send + Sync + 'static,
+    ) -> Self {
+        self.events.set(Arc::new(handler));
+        self
+    }
+
+    /// 订阅用户数据, 需要先登录或设置签名器
+    pub async fn subscribe_user_stream(&self) -> ApiResult<UserStreamSubscription> {
+        match self.signer {
+            Some(_) => self.query(SubscribeUserStreamSignature).await,
+            None => self.query(SubscribeUserStream).await,
+        }
+    }
+
+    /// 取消当前连接上的所有用户数据订阅
+    pub async fn unsubscribe_user_stream(&self) -> ApiResult<EmptyResponse> {
+        self.query(UnsubscribeUserStream::default()).await
+    }
+
+    pub async fn batch_query<P: ApiQuery>(
+        &self,
+        batch_size: usize,
+        params: Vec<P>,
+    ) -> Result<Vec<Response<P::Response>>, ClientError> {
+        let batch_size = batch_size.max(1);
+        let mut results = Vec::with_capacity(params.len());
+        let mut pending = Vec::with_capacity(batch_size);
+        let mut params = params.into_iter().peekable();
+        while let Some(p) = params.next() {
+            if let Err(e) = self.send_in_batch(p, &mut pending).await {
+                for (_, _, permit) in pending {
+                    self.release(permit);
+                }
+                return Err(e);
+            }
+            if pending.len() < batch_size && params.peek().is_some() {
+                continue;
+            }
+            let mut error = None;
+            for (guard, rx, permit) in std::mem::take(&mut pending) {
+                // 出错后不再等待其余响应, drop guard 时移除等待者
+                if error.is_some() {
+                    self.release(permit);
+                    continue;
+                }
+                let resp = self._recv(guard, rx).await.and_then(|resp| {
+                    serde_json::from_str::<Response<P::Response>>(&resp).map_err(|e| e.into())
+                });
+                self.observe(permit, &resp);
+                match resp {
+                    Ok(resp) => results.push(resp),
+                    Err(e) => error = Some(e),
+                }
+            }
+            if let Some(e) = error {
+                return Err(e);
+            }
+        }
+        Ok(results)
+    }
+
+    /// 发送批量请求中的一个, 成功后加入 `pending`
+    async fn send_in_batch<'a, P: ApiQuery>(
+        &'a self,
+        param: P,
+        pending: &mut Vec<(WaitGuard<'a>, Receiver, Option<Permit>)>,
+    ) -> Result<(), ClientError> {
+        self.refresh_clock(P::TYPE).await;
+        let param = self.wrap(P::TYPE, param)?;
+        let permit = self.acquire(&param.other).await?;
+        match self._send(P::METHOD, param).await {
+            Ok((guard, rx)) => {
+                pending.push((guard, rx, permit));
+                Ok(())
+            }
+            Err(e) => {
+                let resp = Err(e);
+                self.observe::<()>(permit, &resp);
+                resp.map(|_| ())
+            }
+        }
+    }
+
+    pub async fn query<P: ApiQuery>(&self, param: P) -> ApiResult<P::Response> {
+        self.refresh_clock(P::TYPE).await;
+        self.query_unsynced(param).await
+    }
+
+    /// 不触发时间校准的请求
+    async fn query_unsynced<P: ApiQuery>(&self, param: P) -> ApiResult<P::Response> {
+        let param = self.wrap(P::TYPE, param)?;
+        let permit = self.acquire(&param.other).await?;
+        let resp = match self._send(P::METHOD, param).await {
+            Ok((guard, rx)) => self
+                ._recv(guard, rx)
+                .await
+                .and_then(|resp| serde_json::from_str(&resp).map_err(|e| e.into())),
+            Err(e) => Err(e),
+        };
+        self.observe(permit, &resp);
+        resp
+    }
+
+    async fn acquire<P: ApiQuery>(&self, param: &P) -> Result<Option<Permit>, ClientError> {
+        match &self.limiter {
+            Some(limiter) => limiter.acquire_for_async(param).await.map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn release(&self, permit: Option<Permit>) {
+        if let (Some(limiter), Some(permit)) = (&self.limiter, permit) {
+            limiter.release(permit);
+        }
+    }
+
+    fn observe<T>(&self, permit: Option<Permit>, resp: &ApiResult<T>) {
+        if let (Some(limiter), Some(permit)) = (&self.limiter, permit) {
+            limiter.observe_for(permit, resp);
+        }
+        self.clock.observe(resp);
+    }
+
+    fn wrap<P: Serialize>(&self, ty: QueryType, param: P) -> Result<ParamWrapper<P>, ClientError> {
+        let param = match ty {
+            QueryType::None => ParamWrapper {
+                recv_window: None,
+                api_key: None,
+                timestamp: 0,
+                signature: None,
+                other: param,
+            },
+            QueryType::Authorized => ParamWrapper {
+                recv_window: Some(self.recv_window),
+                api_key: Some(self.api_key.clone()),
+                timestamp: self.clock.now(),
+                signature: None,
+                other: param,
+            },
+            QueryType::AuthWithoutApiKey => ParamWrapper {
+                recv_window: Some(self.recv_window),
+                api_key: None,
+                timestamp: self.clock.now(),
+                signature: None,
+                other: param,
+            },
+        };
+        match (&self.signer, ty) {
+            (Some(signer), QueryType::Authorized | QueryType::AuthWithoutApiKey) => {
+                Ok(param.sign(signer)?)
+            }
+            _ => Ok(param),
+        }
+    }
+
+    async fn _send<T: Serialize>(
+        &self,
+        method: &'static str,
+        params: T,
+    ) -> Result<(WaitGuard<'_>, Receiver), ClientError> {
+        let id = self.req_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let req = Request {
+            id,
+            method,
+            params,
+            return_rate_limits: self.limiter.as_ref().map(|_| true),
+        };
+        let req = serde_json::to_string(&req)?;
+        tracing::debug!("{req}");
+        let (tx, rx) = oneshot::channel();
+        self.waiters.register(id, tx)?;
+        let guard = WaitGuard {
+            id,
+            waiters: &self.waiters,
+        };
+        self.writer.lock().await.send(&req).await?;
+        Ok((guard, rx))
+    }
+
+    async fn _recv(&self, _guard: WaitGuard<'_>, rx: Receiver) -> Result<String, ClientError> {
+        rx.await
+            .unwrap_or_else(|_| Err(connection_closed("connection closed")))
+    }
+
+    async fn read_loop(
+        mut reader: AsyncStringRecv<ReadHalf<AsyncStream>>,
+        writer: Writer,
+        waiters: Arc<Waiters<Reply>>,
+        events: Events,
+    ) {
+        let reason = loop {
+            let msg = match reader.receive().await {
+                Ok(msg) => msg,
+                Err(e) => break e.to_string(),
+            };
+            match msg.code {
+                OpCode::Ping => {
+                    if let Err(e) = writer.lock().await.pong(&msg.data).await {
+                        break e.to_string();
+                    }
+                    tracing::debug!("got server ping, pong back ...");
+                }
+                OpCode::Text => match decode_frame(&msg.data) {
+                    Ok(Frame::Response(id, resp)) => match waiters.take(id) {
+                        Some(tx) => {
+                            tx.send(resp).ok();
+                        }
+                        None => warn!("drop response without waiter {id}"),
+                    },
+                    Ok(Frame::Unsolicited(msg)) => events.dispatch(msg),
+                    Err(e) => warn!("invalid response {e}: {}", msg.data),
+                },
+                OpCode::Close => break "connection closed by server".to_string(),
+                c => {
+                    tracing::error!("unexpected frame type {c:?}");
+                }
+            }
+        };
+        tracing::debug!("reader exit: {reason}");
+        for (_, tx) in waiters.close() {
+            tx.send(Err(connection_closed(&reason))).ok();
+        }
+    }
+}
+
+impl Drop for AsyncClient {
+    fn drop(&mut self) {
+        self.reader.abort();
+    }
+}
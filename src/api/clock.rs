@@ -0,0 +1,101 @@
+use std::{
+    sync::atomic::{AtomicBool, AtomicI64, Ordering},
+    time::Duration,
+};
+
+use crate::millis_ts;
+
+use super::{ApiResult, ErrorCode};
+
+/// 本地时间与服务器时间的校准
+///
+/// 通过 [`QServerTime`](super::common::QServerTime) 测量服务器时间与本地时间的偏移,
+/// 假设请求往返耗时对称, 偏移为 `server_time - (send_ts + recv_ts) / 2`.
+/// 签名请求的 `timestamp` 使用校准后的时间
+///
+/// [`ClockSync::default`] 不自动校准(`refresh_interval` 为 0), 偏移保持为 0, 除非收到 -1021
+/// 等错误后被 [`ClockSync::invalidate`] 标记. 客户端默认使用这种时钟, 需要定期校准时用
+/// [`ClockSync::new`] 创建并通过 `with_clock` 设置
+#[derive(Debug, Default)]
+pub struct ClockSync {
+    offset: AtomicI64,
+    rtt: AtomicI64,
+    /// 上次校准的本地时间(ms), 0 表示未校准
+    last_sync: AtomicI64,
+    /// 自动校准间隔(ms), 0 表示不自动校准
+    refresh_interval: i64,
+    /// 收到 -1021 等错误后需要立即重新校准
+    stale: AtomicBool,
+}
+
+impl ClockSync {
+    /// 每隔 `refresh_interval` 在发送签名请求前自动校准一次, 为 0 时同 [`ClockSync::default`]
+    pub fn new(refresh_interval: Duration) -> Self {
+        Self {
+            refresh_interval: refresh_interval.as_millis() as i64,
+            ..Default::default()
+        }
+    }
+
+    /// 校准后的当前时间(ms)
+    pub fn now(&self) -> i64 {
+        millis_ts() + self.offset()
+    }
+
+    /// 服务器时间减本地时间(ms)
+    pub fn offset(&self) -> i64 {
+        self.offset.load(Ordering::Relaxed)
+    }
+
+    /// 最近一次校准请求的往返耗时(ms)
+    pub fn rtt(&self) -> i64 {
+        self.rtt.load(Ordering::Relaxed)
+    }
+
+    /// 上次校准的本地时间(ms), 未校准时为 0
+    pub fn last_sync(&self) -> i64 {
+        self.last_sync.load(Ordering::Relaxed)
+    }
+
+    /// 是否需要在下一个签名请求前重新校准
+    pub fn need_refresh(&self) -> bool {
+        if self.stale.load(Ordering::Relaxed) {
+            return true;
+        }
+        self.refresh_interval > 0 && millis_ts() - self.last_sync() >= self.refresh_interval
+    }
+
+    /// 标记需要重新校准, 如收到时间戳超出 recvWindow 的错误
+    pub fn invalidate(&self) {
+        self.stale.store(true, Ordering::Relaxed);
+    }
+
+    /// 记录一次校准结果, 返回新的偏移
+    pub fn record(&self, send_ts: i64, recv_ts: i64, server_time: i64) -> i64 {
+        let rtt = (recv_ts - send_ts).max(0);
+        let offset = server_time - (send_ts + recv_ts) / 2;
+        self.offset.store(offset, Ordering::Relaxed);
+        self.rtt.store(rtt, Ordering::Relaxed);
+        self.last_sync.store(recv_ts, Ordering::Relaxed);
+        self.stale.store(false, Ordering::Relaxed);
+        tracing::debug!("clock synced, offset {offset}ms rtt {rtt}ms");
+        offset
+    }
+
+    /// 时间戳超出 recvWindow 时标记需要重新校准
+    pub(crate) fn observe<T>(&self, resp: &ApiResult<T>) {
+        if let Err(e) = resp
+            && e.api_error()
+                .is_some_and(|e| e.error_code() == ErrorCode::InvalidTimestamp)
+        {
+            self.invalidate();
+        }
+    }
+
+    /// 调用 `query` 获取服务器时间并更新偏移
+    pub fn sync<E>(&self, query: impl FnOnce() -> Result<i64, E>) -> Result<i64, E> {
+        let send_ts = millis_ts();
+        let server_time = query()?;
+        Ok(self.record(send_ts, millis_ts(), server_time))
+    }
+}
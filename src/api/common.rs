@@ -2,6 +2,7 @@ use super::ApiQuery;
 use super::EmptyResponse;
 use super::QueryType;
 use super::RateLimit;
+use crate::rest::RestQuery;
 use rust_decimal::Decimal;
 use serde::Deserialize;
 use serde::Serialize;
@@ -18,6 +19,10 @@ impl ApiQuery for Ping {
     const TYPE: QueryType = QueryType::None;
 }
 
+impl RestQuery for Ping {
+    const PATH: &'static str = "/api/v3/ping";
+}
+
 /// 测试与 WebSocket API 的连通性并获取当前服务器时间
 #[derive(Debug, Clone, Deserialize)]
 pub struct QServerTime;
@@ -30,6 +35,10 @@ impl ApiQuery for QServerTime {
     const TYPE: QueryType = QueryType::None;
 }
 
+impl RestQuery for QServerTime {
+    const PATH: &'static str = "/api/v3/time";
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct ServerTime {
     #[serde(rename = "serverTime")]
@@ -50,6 +59,14 @@ impl ApiQuery for QExchangeInfo {
     type Response = ExchangeInfo;
     const METHOD: &'static str = "exchangeInfo";
     const TYPE: QueryType = QueryType::None;
+
+    fn weight(&self) -> u32 {
+        20
+    }
+}
+
+impl RestQuery for QExchangeInfo {
+    const PATH: &'static str = "/api/v3/exchangeInfo";
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
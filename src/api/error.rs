@@ -0,0 +1,254 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+/// 接口返回的错误信息
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BinanceError {
+    pub code: i64,
+    pub msg: String,
+    /// 部分错误附带的额外信息, 如限频时的 `retryAfter`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<serde_json::Value>,
+}
+
+impl fmt::Display for BinanceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.code, self.msg)
+    }
+}
+
+impl BinanceError {
+    /// 按现货接口的含义解析错误码, 合约使用 [`BinanceError::future_error_code`]
+    pub fn error_code(&self) -> ErrorCode {
+        ErrorCode::spot(self.code)
+    }
+
+    pub fn future_error_code(&self) -> ErrorCode {
+        ErrorCode::future(self.code)
+    }
+
+    pub fn category(&self) -> ErrorCategory {
+        self.error_code().category()
+    }
+
+    /// 临时性错误, 稍后重试可能成功
+    pub fn is_retryable(&self) -> bool {
+        self.category() == ErrorCategory::Retryable
+    }
+
+    /// 请求可能已经执行
+    pub fn is_unknown_outcome(&self) -> bool {
+        self.category() == ErrorCategory::UnknownOutcome
+    }
+
+    pub fn is_auth(&self) -> bool {
+        self.category() == ErrorCategory::Auth
+    }
+
+    pub fn is_filter_violation(&self) -> bool {
+        self.category() == ErrorCategory::FilterViolation
+    }
+
+    pub fn is_rate_limit(&self) -> bool {
+        self.category() == ErrorCategory::RateLimit
+    }
+
+    pub fn is_order_rejected(&self) -> bool {
+        self.category() == ErrorCategory::OrderRejected
+    }
+
+    /// 限频或封禁时服务器给出的解禁时间(ms)
+    pub fn retry_after(&self) -> Option<i64> {
+        self.data.as_ref()?.get("retryAfter")?.as_i64()
+    }
+}
+
+/// 错误码分类
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorCategory {
+    /// 服务器内部错误或时间戳超出 recvWindow, 请求未执行
+    Retryable,
+    /// 请求可能已经执行, 重试前需要先查询状态, 避免重复下单
+    UnknownOutcome,
+    /// api key, 签名或权限错误
+    Auth,
+    /// 价格, 数量等不满足交易对过滤器
+    FilterViolation,
+    /// 超过频率限制
+    RateLimit,
+    /// 下单, 撤单被拒绝
+    OrderRejected,
+    /// 参数错误
+    Request,
+    Other,
+}
+
+macro_rules! error_codes {
+    (
+        common { $($(#[$doc:meta])* $name:ident = $code:literal => $category:ident,)* }
+        spot { $($(#[$s_doc:meta])* $s_name:ident = $s_code:literal => $s_category:ident,)* }
+        future { $($(#[$f_doc:meta])* $f_name:ident = $f_code:literal => $f_category:ident,)* }
+    ) => {
+        /// 已知的错误码, 现货和合约含义不同的错误码分别对应不同的变体
+        ///
+        /// 参考 <https://developers.binance.com/docs/binance-spot-api-docs/errors>
+        /// 和 <https://developers.binance.com/docs/derivatives/usds-margined-futures/error-code>
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum ErrorCode {
+            $($(#[$doc])* $name,)*
+            $($(#[$s_doc])* $s_name,)*
+            $($(#[$f_doc])* $f_name,)*
+            Other(i64),
+        }
+
+        impl ErrorCode {
+            /// 按现货接口的含义解析
+            pub fn spot(code: i64) -> Self {
+                match code {
+                    $($code => ErrorCode::$name,)*
+                    $($s_code => ErrorCode::$s_name,)*
+                    code => ErrorCode::Other(code),
+                }
+            }
+
+            /// 按合约接口的含义解析
+            pub fn future(code: i64) -> Self {
+                match code {
+                    $($code => ErrorCode::$name,)*
+                    $($f_code => ErrorCode::$f_name,)*
+                    code => ErrorCode::Other(code),
+                }
+            }
+
+            pub fn code(&self) -> i64 {
+                match self {
+                    $(ErrorCode::$name => $code,)*
+                    $(ErrorCode::$s_name => $s_code,)*
+                    $(ErrorCode::$f_name => $f_code,)*
+                    ErrorCode::Other(code) => *code,
+                }
+            }
+
+            pub fn category(&self) -> ErrorCategory {
+                match self {
+                    $(ErrorCode::$name => ErrorCategory::$category,)*
+                    $(ErrorCode::$s_name => ErrorCategory::$s_category,)*
+                    $(ErrorCode::$f_name => ErrorCategory::$f_category,)*
+                    ErrorCode::Other(_) => ErrorCategory::Other,
+                }
+            }
+        }
+
+        /// 按现货接口的含义解析, 合约使用 [`ErrorCode::future`]
+        impl From<i64> for ErrorCode {
+            fn from(code: i64) -> Self {
+                Self::spot(code)
+            }
+        }
+    };
+}
+
+error_codes! {
+    common {
+        /// 处理请求时发生未知错误, 请求可能已经执行
+        Unknown = -1000 => UnknownOutcome,
+        Disconnected = -1001 => Retryable,
+        Unauthorized = -1002 => Auth,
+        TooManyRequests = -1003 => RateLimit,
+        /// 收到后端的意外响应, 请求的执行状态未知
+        UnexpectedResponse = -1006 => UnknownOutcome,
+        /// 等待后端响应超时, 请求的执行状态未知
+        Timeout = -1007 => UnknownOutcome,
+        ServerBusy = -1008 => Retryable,
+        /// 不满足交易对过滤器(Filter failure: ...)
+        InvalidMessage = -1013 => FilterViolation,
+        UnknownOrderComposition = -1014 => Request,
+        TooManyOrders = -1015 => RateLimit,
+        ServiceShuttingDown = -1016 => Retryable,
+        UnsupportedOperation = -1020 => Request,
+        /// 时间戳超出 recvWindow, 校准时间后重试
+        InvalidTimestamp = -1021 => Retryable,
+        InvalidSignature = -1022 => Auth,
+        NotAuthorized = -1099 => Auth,
+        IllegalChars = -1100 => Request,
+        TooManyParameters = -1101 => Request,
+        MandatoryParamEmptyOrMalformed = -1102 => Request,
+        UnknownParam = -1103 => Request,
+        UnreadParameters = -1104 => Request,
+        ParamEmpty = -1105 => Request,
+        ParamNotRequired = -1106 => Request,
+        BadPrecision = -1111 => FilterViolation,
+        NoDepth = -1112 => OrderRejected,
+        TifNotRequired = -1114 => Request,
+        InvalidTif = -1115 => Request,
+        InvalidOrderType = -1116 => Request,
+        InvalidSide = -1117 => Request,
+        EmptyNewClOrdId = -1118 => Request,
+        EmptyOrgClOrdId = -1119 => Request,
+        BadInterval = -1120 => Request,
+        BadSymbol = -1121 => Request,
+        InvalidListenKey = -1125 => Auth,
+        MoreThanXxHours = -1127 => Request,
+        OptionalParamsBadCombo = -1128 => Request,
+        InvalidParameter = -1130 => Request,
+        /// 新订单被拒绝, 如余额不足
+        NewOrderRejected = -2010 => OrderRejected,
+        CancelRejected = -2011 => OrderRejected,
+        NoSuchOrder = -2013 => OrderRejected,
+        BadApiKeyFmt = -2014 => Auth,
+        RejectedMbxKey = -2015 => Auth,
+        NoTradingWindow = -2016 => OrderRejected,
+        /// 合约保证金不足
+        MarginNotSufficient = -2019 => OrderRejected,
+        UnableToFill = -2020 => OrderRejected,
+        PositionNotSufficient = -2024 => OrderRejected,
+        MaxOpenOrderExceeded = -2025 => OrderRejected,
+        MaxLeverageRatio = -2027 => OrderRejected,
+        MinLeverageRatio = -2028 => OrderRejected,
+        PriceLessThanZero = -4001 => FilterViolation,
+        PriceGreaterThanMaxPrice = -4002 => FilterViolation,
+        QtyLessThanZero = -4003 => FilterViolation,
+        QtyLessThanMinQty = -4004 => FilterViolation,
+        QtyGreaterThanMaxQty = -4005 => FilterViolation,
+        PriceLessThanMinPrice = -4013 => FilterViolation,
+        PriceNotIncreasedByTickSize = -4014 => FilterViolation,
+        QtyNotIncreasedByStepSize = -4023 => FilterViolation,
+        PriceOutOfPercentRange = -4131 => FilterViolation,
+        MinNotional = -4164 => FilterViolation,
+    }
+    spot {
+        /// 撤单或下新单其中一步失败
+        CancelReplacePartiallyFailed = -2021 => OrderRejected,
+        /// 撤单和下新单都失败
+        CancelReplaceFailed = -2022 => OrderRejected,
+    }
+    future {
+        /// 条件单会立即触发
+        OrderWouldImmediatelyTrigger = -2021 => OrderRejected,
+        ReduceOnlyReject = -2022 => OrderRejected,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(code: i64) -> BinanceError {
+        BinanceError {
+            code,
+            msg: Default::default(),
+            data: None,
+        }
+    }
+
+    #[test]
+    fn unknown_outcome_is_not_retryable() {
+        for code in [-1000, -1006, -1007] {
+            assert!(error(code).is_unknown_outcome(), "{code}");
+            assert!(!error(code).is_retryable(), "{code}");
+        }
+        assert!(error(-1001).is_retryable());
+        assert!(error(-1021).is_retryable());
+    }
+}
@@ -6,7 +6,7 @@ use serde::{
     de::{SeqAccess, Unexpected, Visitor},
 };
 
-use crate::realtime_market::Interval;
+use crate::{realtime_market::Interval, rest::RestQuery};
 
 use super::{ApiQuery, QueryType};
 
@@ -60,6 +60,46 @@ impl ApiQuery for QLatestPrice {
     type Response = LatestPrices;
     const METHOD: &'static str = "ticker.price";
     const TYPE: QueryType = QueryType::None;
+
+    fn weight(&self) -> u32 {
+        match self {
+            QLatestPrice::Symbol(_) => 2,
+            _ => 4,
+        }
+    }
+
+    fn future_weight(&self) -> u32 {
+        match self {
+            QLatestPrice::Symbol(_) => 1,
+            _ => 2,
+        }
+    }
+}
+
+impl RestQuery for QLatestPrice {
+    const PATH: &'static str = "/api/v3/ticker/price";
+}
+
+/// 合约最新价格, 不指定交易对时返回所有交易对
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct QFutureLatestPrice {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub symbol: Option<String>,
+}
+
+impl ApiQuery for QFutureLatestPrice {
+    type Response = LatestPrices;
+    const METHOD: &'static str = "ticker.price";
+    const TYPE: QueryType = QueryType::None;
+
+    fn weight(&self) -> u32 {
+        if self.symbol.is_some() { 1 } else { 2 }
+    }
+}
+
+impl RestQuery for QFutureLatestPrice {
+    const PATH: &'static str = "/fapi/v2/ticker/price";
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -76,18 +116,22 @@ pub struct QDepth {
     pub limit: Option<u64>,
 }
 
-#[derive(Debug, Deserialize)]
+/// 深度, 也用于 `<symbol>@depth<levels>` 有限档深度推送(合约推送使用 `u`, `b`, `a` 字段)
+#[derive(Debug, Clone, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Depth {
+    #[serde(alias = "u")]
     pub last_update_id: usize,
     /// 买单
+    #[serde(alias = "b")]
     pub bids: Vec<PriceVol>,
     /// 卖单
+    #[serde(alias = "a")]
     pub asks: Vec<PriceVol>,
 }
 
 /// (价格, 数量)
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub struct PriceVol(pub Decimal, pub Decimal);
 
 impl<'de> Deserialize<'de> for PriceVol {
@@ -131,6 +175,28 @@ impl ApiQuery for QDepth {
     type Response = Depth;
     const METHOD: &'static str = "depth";
     const TYPE: QueryType = QueryType::None;
+
+    fn weight(&self) -> u32 {
+        match self.limit.unwrap_or(100) {
+            0..=100 => 5,
+            101..=500 => 25,
+            501..=1000 => 50,
+            _ => 250,
+        }
+    }
+
+    fn future_weight(&self) -> u32 {
+        match self.limit.unwrap_or(500) {
+            0..=50 => 2,
+            51..=100 => 5,
+            101..=500 => 10,
+            _ => 20,
+        }
+    }
+}
+
+impl RestQuery for QDepth {
+    const PATH: &'static str = "/api/v3/depth";
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -160,9 +226,18 @@ impl ApiQuery for QRecentTrade {
     type Response = Vec<TradeRecord>;
     const METHOD: &'static str = "trades.recent";
     const TYPE: QueryType = QueryType::None;
+
+    fn weight(&self) -> u32 {
+        25
+    }
+}
+
+impl RestQuery for QRecentTrade {
+    const PATH: &'static str = "/api/v3/trades";
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct QHistoryTrade {
     pub symbol: String,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -175,9 +250,19 @@ impl ApiQuery for QHistoryTrade {
     type Response = Vec<TradeRecord>;
     const METHOD: &'static str = "trades.historical";
     const TYPE: QueryType = QueryType::None;
+
+    fn weight(&self) -> u32 {
+        25
+    }
 }
 
+impl RestQuery for QHistoryTrade {
+    const PATH: &'static str = "/api/v3/historicalTrades";
+}
+
+/// 归集交易, 指定 `from_id` 时从该ID开始, 否则返回最近的成交
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
 pub struct QAggTrade {
     pub symbol: String,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -190,7 +275,7 @@ pub struct QAggTrade {
     pub limit: Option<u64>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct AggTrade {
     #[serde(rename = "a")]
@@ -207,10 +292,25 @@ pub struct AggTrade {
     pub time: i64,
     #[serde(rename = "m")]
     pub is_maker: bool,
-    #[serde(rename = "M")]
+    /// 合约没有此字段
+    #[serde(rename = "M", default)]
     pub is_best_match: bool,
 }
 
+impl ApiQuery for QAggTrade {
+    type Response = Vec<AggTrade>;
+    const METHOD: &'static str = "trades.aggregate";
+    const TYPE: QueryType = QueryType::None;
+
+    fn weight(&self) -> u32 {
+        4
+    }
+}
+
+impl RestQuery for QAggTrade {
+    const PATH: &'static str = "/api/v3/aggTrades";
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 #[serde(rename_all = "camelCase")]
 pub struct QKline {
@@ -345,6 +445,14 @@ impl ApiQuery for QKline {
     type Response = Vec<Kline>;
     const METHOD: &'static str = "klines";
     const TYPE: QueryType = QueryType::None;
+
+    fn weight(&self) -> u32 {
+        2
+    }
+}
+
+impl RestQuery for QKline {
+    const PATH: &'static str = "/api/v3/klines";
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -366,6 +474,14 @@ impl ApiQuery for QUIKline {
     type Response = Vec<Kline>;
     const METHOD: &'static str = "uiKlines";
     const TYPE: QueryType = QueryType::None;
+
+    fn weight(&self) -> u32 {
+        2
+    }
+}
+
+impl RestQuery for QUIKline {
+    const PATH: &'static str = "/api/v3/uiKlines";
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -387,6 +503,14 @@ impl ApiQuery for QAvgPrice {
     type Response = AvgPrice;
     const METHOD: &'static str = "avgPrice";
     const TYPE: QueryType = QueryType::None;
+
+    fn weight(&self) -> u32 {
+        2
+    }
+}
+
+impl RestQuery for QAvgPrice {
+    const PATH: &'static str = "/api/v3/avgPrice";
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -460,6 +584,21 @@ impl ApiQuery for QMiniTicker {
     type Response = H24TickerResult;
     const METHOD: &'static str = "ticker.24hr";
     const TYPE: QueryType = QueryType::None;
+
+    fn weight(&self) -> u32 {
+        match self {
+            QMiniTicker::Single { .. } => 2,
+            QMiniTicker::Many { symbols, .. } => match symbols.len() {
+                0..=20 => 2,
+                21..=100 => 40,
+                _ => 80,
+            },
+        }
+    }
+}
+
+impl RestQuery for QMiniTicker {
+    const PATH: &'static str = "/api/v3/ticker/24hr";
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -488,6 +627,14 @@ impl ApiQuery for QSingleTickerBook {
     type Response = SingleTickerBook;
     const METHOD: &'static str = "ticker.book";
     const TYPE: QueryType = QueryType::None;
+
+    fn weight(&self) -> u32 {
+        2
+    }
+}
+
+impl RestQuery for QSingleTickerBook {
+    const PATH: &'static str = "/api/v3/ticker/bookTicker";
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -498,4 +645,16 @@ impl ApiQuery for QAllTickerBook {
     type Response = Vec<SingleTickerBook>;
     const METHOD: &'static str = "ticker.book";
     const TYPE: QueryType = QueryType::None;
+
+    fn weight(&self) -> u32 {
+        4
+    }
+
+    fn future_weight(&self) -> u32 {
+        5
+    }
+}
+
+impl RestQuery for QAllTickerBook {
+    const PATH: &'static str = "/api/v3/ticker/bookTicker";
 }
@@ -0,0 +1,389 @@
+use std::{
+    sync::{
+        Mutex,
+        atomic::{AtomicI64, Ordering},
+    },
+    time::Duration,
+};
+
+use crate::millis_ts;
+
+use super::{ApiQuery, ApiResult, ClientError, RateLimit};
+
+/// 已占用额度的请求, 收到响应后释放
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Permit {
+    weight: u32,
+    is_order: bool,
+}
+
+impl Permit {
+    fn new<P: ApiQuery>(limiter: &RateLimiter, param: &P) -> Self {
+        let weight = match limiter.future {
+            true => param.future_weight(),
+            false => param.weight(),
+        };
+        Self {
+            weight,
+            is_order: P::IS_ORDER,
+        }
+    }
+}
+
+/// 即将超过限制时的处理方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RateLimitMode {
+    /// 等待到下一个统计周期再发送
+    #[default]
+    Delay,
+    /// 直接返回 [`ClientError::RateLimited`]
+    Reject,
+}
+
+/// 客户端限频
+///
+/// 按 `rateLimits` 中的每个限制(REQUEST_WEIGHT, ORDERS, RAW_REQUESTS)分别计数,
+/// 发送请求前检查是否会超限, 收到响应后用服务器返回的计数校正本地计数.
+/// 多个客户端共用同一个 IP 或账户时可以通过 `Arc` 共享
+#[derive(Debug)]
+pub struct RateLimiter {
+    mode: RateLimitMode,
+    /// 按合约接口的权重计数
+    future: bool,
+    counters: Mutex<Vec<Counter>>,
+    /// 收到 429/418 后服务器要求的解禁时间(ms)
+    banned_until: AtomicI64,
+}
+
+#[derive(Debug)]
+struct Counter {
+    limit: RateLimit,
+    /// 当前统计周期的起始时间(ms)
+    window_start: i64,
+    /// 已发送但还没有收到响应的请求消耗, 不包含在服务器返回的计数中
+    pending: i64,
+}
+
+impl Counter {
+    fn interval_ms(&self) -> i64 {
+        interval_ms(&self.limit.interval) * self.limit.interval_num.max(1)
+    }
+
+    fn roll(&mut self, now: i64) {
+        let window = self.interval_ms();
+        let start = now - now.rem_euclid(window);
+        if start != self.window_start {
+            self.window_start = start;
+            self.limit.count = 0;
+            self.pending = 0;
+        }
+    }
+
+    fn cost(&self, weight: u32, is_order: bool) -> i64 {
+        match self.limit.rate_limit_type.as_str() {
+            "REQUEST_WEIGHT" => weight as i64,
+            "ORDERS" => is_order as i64,
+            "RAW_REQUESTS" => 1,
+            _ => 0,
+        }
+    }
+
+    fn same_limit(&self, other: &RateLimit) -> bool {
+        self.limit.rate_limit_type == other.rate_limit_type
+            && self.limit.interval == other.interval
+            && self.limit.interval_num == other.interval_num
+    }
+}
+
+fn interval_ms(interval: &str) -> i64 {
+    match interval {
+        "SECOND" => 1_000,
+        "MINUTE" => 60_000,
+        "HOUR" => 3_600_000,
+        "DAY" => 86_400_000,
+        _ => 60_000,
+    }
+}
+
+impl RateLimiter {
+    /// `limits` 一般来自 [`ExchangeInfo::rate_limits`](super::common::ExchangeInfo),
+    /// 为空时从服务器返回的 `rateLimits` 中学习
+    pub fn new(limits: Vec<RateLimit>, mode: RateLimitMode) -> Self {
+        let now = millis_ts();
+        let counters = limits
+            .into_iter()
+            .map(|limit| {
+                let mut counter = Counter {
+                    limit,
+                    window_start: 0,
+                    pending: 0,
+                };
+                counter.roll(now);
+                counter
+            })
+            .collect();
+        Self {
+            mode,
+            future: false,
+            counters: Mutex::new(counters),
+            banned_until: AtomicI64::new(0),
+        }
+    }
+
+    /// 用于合约连接, 按 [`ApiQuery::future_weight`] 计算请求权重
+    pub fn with_future_weights(mut self) -> Self {
+        self.future = true;
+        self
+    }
+
+    pub fn mode(&self) -> RateLimitMode {
+        self.mode
+    }
+
+    /// 尝试占用额度, 超限时返回 [`ClientError::RateLimited`] 和需要等待的时间,
+    /// 单个请求超过限制时返回 [`ClientError::ExceedsRateLimit`]
+    pub fn try_acquire(&self, weight: u32, is_order: bool) -> Result<(), ClientError> {
+        let now = millis_ts();
+        let mut counters = self.counters.lock().unwrap();
+        let mut wait = self.banned_until.load(Ordering::Relaxed) - now;
+        for counter in counters.iter_mut() {
+            counter.roll(now);
+            let cost = counter.cost(weight, is_order);
+            let limit = counter.limit.limit;
+            if cost > limit && limit > 0 {
+                return Err(ClientError::ExceedsRateLimit { cost, limit });
+            }
+            if cost > 0 && counter.limit.count + cost > limit {
+                wait = wait.max(counter.window_start + counter.interval_ms() - now);
+            }
+        }
+        if wait > 0 {
+            return Err(ClientError::RateLimited(Duration::from_millis(wait as u64)));
+        }
+        for counter in counters.iter_mut() {
+            let cost = counter.cost(weight, is_order);
+            counter.limit.count += cost;
+            counter.pending += cost;
+        }
+        Ok(())
+    }
+
+    /// 按 [`RateLimitMode`] 占用额度, `Delay` 模式下会阻塞当前线程
+    pub fn acquire(&self, weight: u32, is_order: bool) -> Result<(), ClientError> {
+        loop {
+            match self.try_acquire(weight, is_order) {
+                Err(ClientError::RateLimited(wait)) if self.mode == RateLimitMode::Delay => {
+                    tracing::debug!("rate limited, wait {wait:?}");
+                    std::thread::sleep(wait);
+                }
+                resp => return resp,
+            }
+        }
+    }
+
+    /// [`acquire`](Self::acquire) 的异步版本
+    #[cfg(feature = "async")]
+    pub async fn acquire_async(&self, weight: u32, is_order: bool) -> Result<(), ClientError> {
+        loop {
+            match self.try_acquire(weight, is_order) {
+                Err(ClientError::RateLimited(wait)) if self.mode == RateLimitMode::Delay => {
+                    tracing::debug!("rate limited, wait {wait:?}");
+                    tokio::time::sleep(wait).await;
+                }
+                resp => return resp,
+            }
+        }
+    }
+
+    pub(crate) fn acquire_for<P: ApiQuery>(&self, param: &P) -> Result<Permit, ClientError> {
+        let permit = Permit::new(self, param);
+        self.acquire(permit.weight, permit.is_order)?;
+        Ok(permit)
+    }
+
+    #[cfg(feature = "async")]
+    pub(crate) async fn acquire_for_async<P: ApiQuery>(
+        &self,
+        param: &P,
+    ) -> Result<Permit, ClientError> {
+        let permit = Permit::new(self, param);
+        self.acquire_async(permit.weight, permit.is_order).await?;
+        Ok(permit)
+    }
+
+    /// 请求结束(包括超时和失败), 不再计入未返回的请求
+    pub(crate) fn release(&self, permit: Permit) {
+        let now = millis_ts();
+        for counter in self.counters.lock().unwrap().iter_mut() {
+            counter.roll(now);
+            let cost = counter.cost(permit.weight, permit.is_order);
+            counter.pending = (counter.pending - cost).max(0);
+        }
+    }
+
+    /// 请求结束后释放额度并更新计数
+    pub(crate) fn observe_for<T>(&self, permit: Permit, resp: &ApiResult<T>) {
+        self.release(permit);
+        self.observe(resp);
+    }
+
+    /// 从响应(包括错误响应)中更新计数, 429/418 时暂停发送直到 `retryAfter`
+    pub(crate) fn observe<T>(&self, resp: &ApiResult<T>) {
+        match resp {
+            Ok(resp) => self.update(&resp.rate_limits),
+            Err(ClientError::ApiError(err)) => {
+                self.update(&err.rate_limits);
+                if err.status == 429 || err.status == 418 {
+                    let retry_after = err
+                        .error
+                        .retry_after()
+                        .unwrap_or_else(|| millis_ts() + 60_000);
+                    tracing::warn!("rate limit exceeded, pause until {retry_after}");
+                    self.banned_until.fetch_max(retry_after, Ordering::Relaxed);
+                }
+            }
+            Err(_) => {}
+        }
+    }
+
+    /// 用服务器返回的计数校正本地计数, 本地计数为服务器计数加上还没有返回的请求.
+    /// `limit` 为 0 表示未知(如 REST 响应头), 只校正计数
+    pub fn update(&self, limits: &[RateLimit]) {
+        let now = millis_ts();
+        let mut counters = self.counters.lock().unwrap();
+        for limit in limits {
+            match counters.iter_mut().find(|c| c.same_limit(limit)) {
+                Some(counter) => {
+                    counter.roll(now);
+                    counter.limit.count = limit.count + counter.pending;
+                    if limit.limit > 0 {
+                        counter.limit.limit = limit.limit;
+                    }
+                }
+                None if limit.limit <= 0 => {}
+                None => {
+                    let mut counter = Counter {
+                        limit: limit.clone(),
+                        window_start: 0,
+                        pending: 0,
+                    };
+                    counter.roll(now);
+                    counter.limit.count = limit.count;
+                    counters.push(counter);
+                }
+            }
+        }
+    }
+
+    /// 当前各个限制的使用情况
+    pub fn usage(&self) -> Vec<RateLimit> {
+        let now = millis_ts();
+        let mut counters = self.counters.lock().unwrap();
+        counters
+            .iter_mut()
+            .map(|c| {
+                c.roll(now);
+                c.limit.clone()
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::{market::QLatestPrice, trade::FutureOrderSpec};
+
+    fn limit(ty: &str, limit: i64, count: i64) -> RateLimit {
+        RateLimit {
+            rate_limit_type: ty.to_string(),
+            interval: "DAY".to_string(),
+            interval_num: 1,
+            limit,
+            count,
+        }
+    }
+
+    fn counts(limiter: &RateLimiter) -> Vec<i64> {
+        limiter.usage().iter().map(|l| l.count).collect()
+    }
+
+    #[test]
+    fn reject_when_window_full() {
+        let limiter = RateLimiter::new(vec![limit("REQUEST_WEIGHT", 10, 0)], RateLimitMode::Reject);
+        limiter.acquire(6, false).unwrap();
+        assert!(matches!(
+            limiter.acquire(6, false),
+            Err(ClientError::RateLimited(_))
+        ));
+        limiter.acquire(4, false).unwrap();
+        assert_eq!(counts(&limiter), vec![10]);
+    }
+
+    #[test]
+    fn cost_over_limit_fails_without_waiting() {
+        let limiter = RateLimiter::new(vec![limit("REQUEST_WEIGHT", 10, 0)], RateLimitMode::Delay);
+        assert!(matches!(
+            limiter.acquire(11, false),
+            Err(ClientError::ExceedsRateLimit {
+                cost: 11,
+                limit: 10
+            })
+        ));
+        assert_eq!(counts(&limiter), vec![0]);
+    }
+
+    #[test]
+    fn orders_counted_without_weight() {
+        let limiter = RateLimiter::new(
+            vec![limit("REQUEST_WEIGHT", 100, 0), limit("ORDERS", 100, 0)],
+            RateLimitMode::Reject,
+        );
+        let permit = limiter.acquire_for(&FutureOrderSpec::default()).unwrap();
+        assert_eq!(counts(&limiter), vec![0, 1]);
+        limiter.release(permit);
+        limiter.acquire(1, false).unwrap();
+        assert_eq!(counts(&limiter), vec![1, 1]);
+    }
+
+    #[test]
+    fn future_weights() {
+        let query = QLatestPrice::Symbol("BTCUSDT".into());
+        let spot = RateLimiter::new(vec![limit("REQUEST_WEIGHT", 100, 0)], RateLimitMode::Reject);
+        spot.acquire_for(&query).unwrap();
+        assert_eq!(counts(&spot), vec![2]);
+        let future = RateLimiter::new(vec![limit("REQUEST_WEIGHT", 100, 0)], RateLimitMode::Reject)
+            .with_future_weights();
+        future.acquire_for(&query).unwrap();
+        assert_eq!(counts(&future), vec![1]);
+    }
+
+    #[test]
+    fn server_count_plus_pending() {
+        let limiter =
+            RateLimiter::new(vec![limit("REQUEST_WEIGHT", 100, 0)], RateLimitMode::Reject);
+        let first = limiter
+            .acquire_for(&QLatestPrice::Symbol("A".into()))
+            .unwrap();
+        limiter
+            .acquire_for(&QLatestPrice::Symbol("B".into()))
+            .unwrap();
+        assert_eq!(counts(&limiter), vec![4]);
+        // 服务器计数包含第一个请求, 可以低于本地计数
+        limiter.release(first);
+        limiter.update(&[limit("REQUEST_WEIGHT", 100, 2)]);
+        assert_eq!(counts(&limiter), vec![4]);
+        limiter.update(&[limit("REQUEST_WEIGHT", 100, 0)]);
+        assert_eq!(counts(&limiter), vec![2]);
+    }
+
+    #[test]
+    fn learn_limits_from_response() {
+        let limiter = RateLimiter::new(vec![], RateLimitMode::Reject);
+        limiter.update(&[limit("REQUEST_WEIGHT", 10, 9)]);
+        assert!(matches!(
+            limiter.acquire(2, false),
+            Err(ClientError::RateLimited(_))
+        ));
+    }
+}
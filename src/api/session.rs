@@ -1,9 +1,11 @@
-use ed25519_dalek::{ed25519::signature::SignerMut, pkcs8::DecodePrivateKey};
 use serde::{Deserialize, Serialize};
 
 use crate::millis_ts;
 
-use super::{ApiQuery, QueryType};
+use super::{
+    ApiQuery, QueryType,
+    sign::{Ed25519Signer, KeyType, RequestSigner, SignError, Signer, new_signer},
+};
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct QSessionStatus;
@@ -14,6 +16,10 @@ impl ApiQuery for QSessionStatus {
     type Response = SessionStatus;
     const METHOD: &'static str = "session.status";
     const TYPE: QueryType = QueryType::None;
+
+    fn weight(&self) -> u32 {
+        2
+    }
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -25,6 +31,10 @@ impl ApiQuery for Logout {
     type Response = SessionStatus;
     const METHOD: &'static str = "session.logout";
     const TYPE: QueryType = QueryType::None;
+
+    fn weight(&self) -> u32 {
+        2
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -41,26 +51,48 @@ pub struct SessionStatus {
 #[serde(rename_all = "camelCase")]
 pub struct Logon {
     pub api_key: String,
+    /// PEM 格式私钥或 HMAC secret
     pub perm_key: String,
+    #[serde(default)]
+    pub key_type: KeyType,
 }
 
 impl Logon {
-    pub fn sign(&self, recv_window: i64) -> SignedLogon {
-        use base64::prelude::*;
-        let timestamp = millis_ts();
-        let qs = format!(
-            "apiKey={}&recvWindow={recv_window}&timestamp={timestamp}",
-            self.api_key,
+    pub fn signer(&self) -> Result<Box<dyn Signer>, SignError> {
+        new_signer(self.key_type, &self.perm_key)
+    }
+
+    /// 用于逐个请求签名, HMAC 和 RSA key 只能使用这种方式
+    pub fn request_signer(&self) -> Result<RequestSigner, SignError> {
+        Ok(RequestSigner::new(
+            self.api_key.clone(),
+            self.signer()?.into(),
+        ))
+    }
+
+    pub fn sign(&self, recv_window: i64) -> Result<SignedLogon, SignError> {
+        self.sign_at(recv_window, millis_ts())
+    }
+
+    /// 使用指定的时间戳签名, 如 [`ClockSync::now`](super::clock::ClockSync::now).
+    /// session.logon 只支持 Ed25519 key
+    pub fn sign_at(&self, recv_window: i64, timestamp: i64) -> Result<SignedLogon, SignError> {
+        if self.key_type != KeyType::Ed25519 {
+            return Err(SignError::LogonUnsupported(self.key_type));
+        }
+        let signer = self.signer()?;
+        let signed = SignOn::with_signer(
+            self.api_key.clone(),
+            signer.as_ref(),
+            recv_window,
+            timestamp,
         );
-        let mut sign_key =
-            ed25519_dalek::SigningKey::from_pkcs8_pem(&self.perm_key).expect("invalid key data");
-        let signature = BASE64_STANDARD.encode(sign_key.sign(qs.as_bytes()).to_bytes());
-        SignedLogon {
-            api_key: self.api_key.clone(),
-            signature,
+        Ok(SignedLogon {
+            api_key: signed.api_key,
+            signature: signed.signature,
             recv_window,
             timestamp,
-        }
+        })
     }
 }
 
@@ -77,6 +109,10 @@ impl ApiQuery for SignedLogon {
     type Response = SessionStatus;
     const METHOD: &'static str = "session.logon";
     const TYPE: QueryType = QueryType::None;
+
+    fn weight(&self) -> u32 {
+        2
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -89,12 +125,25 @@ pub struct SignOn {
 }
 
 impl SignOn {
-    pub fn new(api_key: String, private_key: &str, recv_window: i64, timestamp: i64) -> Self {
-        use base64::prelude::*;
+    /// `private_key` 为 Ed25519 PKCS#8 PEM 格式私钥
+    pub fn new(
+        api_key: String,
+        private_key: &str,
+        recv_window: i64,
+        timestamp: i64,
+    ) -> Result<Self, SignError> {
+        let signer = Ed25519Signer::from_pem(private_key)?;
+        Ok(Self::with_signer(api_key, &signer, recv_window, timestamp))
+    }
+
+    pub fn with_signer(
+        api_key: String,
+        signer: &dyn Signer,
+        recv_window: i64,
+        timestamp: i64,
+    ) -> Self {
         let qs = format!("apiKey={api_key}&recvWindow={recv_window}&timestamp={timestamp}");
-        let mut sign_key =
-            ed25519_dalek::SigningKey::from_pkcs8_pem(private_key).expect("invalid key data");
-        let signature = BASE64_STANDARD.encode(sign_key.sign(qs.as_bytes()).to_bytes());
+        let signature = signer.sign(qs.as_bytes());
         Self {
             api_key,
             recv_window,
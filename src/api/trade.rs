@@ -1,9 +1,12 @@
 use serde::{Deserialize, Serialize};
 
-use crate::{FutureOrderType, OrderSide, OrderStatus, OrderType, TimeInForce};
+use crate::{
+    FutureOrderType, OrderSide, OrderStatus, OrderType, TimeInForce,
+    rest::{RestQuery, Verb},
+};
 use rust_decimal::Decimal;
 
-use super::{ApiQuery, QueryType};
+use super::{ApiQuery, BinanceError, ErrorCode, QueryType};
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 #[serde(rename_all = "camelCase")]
@@ -18,6 +21,14 @@ impl ApiQuery for QPositionRisk {
     const METHOD: &'static str = "v2/account.position";
 
     const TYPE: QueryType = QueryType::AuthWithoutApiKey;
+
+    fn weight(&self) -> u32 {
+        5
+    }
+}
+
+impl RestQuery for QPositionRisk {
+    const PATH: &'static str = "/fapi/v2/positionRisk";
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -64,6 +75,12 @@ impl ApiQuery for OrderSpec {
     type Response = CreateOrderResponse;
     const METHOD: &'static str = "order.place";
     const TYPE: QueryType = QueryType::AuthWithoutApiKey;
+    const IS_ORDER: bool = true;
+}
+
+impl RestQuery for OrderSpec {
+    const PATH: &'static str = "/api/v3/order";
+    const VERB: Verb = Verb::Post;
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -78,6 +95,15 @@ impl ApiQuery for TestOrderSpec {
     type Response = CreateOrderResponse;
     const METHOD: &'static str = "order.test";
     const TYPE: QueryType = QueryType::AuthWithoutApiKey;
+
+    fn weight(&self) -> u32 {
+        if self.compute_commission_rates { 20 } else { 1 }
+    }
+}
+
+impl RestQuery for TestOrderSpec {
+    const PATH: &'static str = "/api/v3/order/test";
+    const VERB: Verb = Verb::Post;
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -93,6 +119,14 @@ impl ApiQuery for QOrderStatus {
     type Response = QueriedOrder;
     const METHOD: &'static str = "order.status";
     const TYPE: QueryType = QueryType::AuthWithoutApiKey;
+
+    fn weight(&self) -> u32 {
+        4
+    }
+}
+
+impl RestQuery for QOrderStatus {
+    const PATH: &'static str = "/api/v3/order";
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -121,6 +155,11 @@ impl ApiQuery for CancelOrder {
     const TYPE: QueryType = QueryType::AuthWithoutApiKey;
 }
 
+impl RestQuery for CancelOrder {
+    const PATH: &'static str = "/api/v3/order";
+    const VERB: Verb = Verb::Delete;
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 #[serde(rename_all = "camelCase")]
 pub struct FutureCancelOrder {
@@ -136,6 +175,11 @@ impl ApiQuery for FutureCancelOrder {
     const TYPE: QueryType = QueryType::AuthWithoutApiKey;
 }
 
+impl RestQuery for FutureCancelOrder {
+    const PATH: &'static str = "/fapi/v1/order";
+    const VERB: Verb = Verb::Delete;
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
 pub enum PositionSide {
     BOTH,
@@ -183,6 +227,17 @@ impl ApiQuery for FutureOrderSpec {
     type Response = FutureOrderResult;
     const METHOD: &'static str = "order.place";
     const TYPE: QueryType = QueryType::AuthWithoutApiKey;
+    const IS_ORDER: bool = true;
+
+    /// 合约下单不占用 IP 权重, 只计入下单频率限制(ORDERS)
+    fn weight(&self) -> u32 {
+        0
+    }
+}
+
+impl RestQuery for FutureOrderSpec {
+    const PATH: &'static str = "/fapi/v1/order";
+    const VERB: Verb = Verb::Post;
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -278,11 +333,21 @@ impl CreateOrderResponse {
             CreateOrderResponse::Full(o) => o.order_id,
         }
     }
+
+    /// `ACK` 类型的响应没有订单状态
+    pub fn status(&self) -> Option<&OrderStatus> {
+        match self {
+            CreateOrderResponse::Ack(_) => None,
+            CreateOrderResponse::Result(o) => Some(&o.status),
+            CreateOrderResponse::Full(o) => Some(&o.status),
+        }
+    }
 }
 
+/// 查询到的订单, 合约订单使用 [`FutureQueriedOrder`]
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 #[serde(rename_all = "camelCase")]
-pub struct QueriedOrder {
+pub struct QueriedOrder<T = OrderType> {
     /// 交易对
     pub symbol: String,
     /// 系统订单ID
@@ -319,7 +384,7 @@ pub struct QueriedOrder {
     pub time_in_force: TimeInForce,
     /// 订单类型， 比如市价单，现价单等
     #[serde(rename = "type")]
-    pub order_type: OrderType,
+    pub order_type: T,
     /// 订单方向
     pub side: OrderSide,
     /// 止损价格
@@ -338,9 +403,15 @@ pub struct QueriedOrder {
     /// 原始交易金额
     #[serde(default)]
     pub orig_quote_order_qty: Decimal,
+    #[serde(default)]
     pub self_trade_prevention_mode: String,
+    /// 合约 GTD 订单的自动取消时间
+    #[serde(default)]
+    pub good_till_date: i64,
 }
 
+pub type FutureQueriedOrder = QueriedOrder<FutureOrderType>;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct OrderAck {
@@ -445,3 +516,425 @@ pub struct OrderFill {
     /// 手续费的币种
     pub commission_asset: String,
 }
+
+/// 当前挂单, 不指定交易对时返回所有交易对的挂单
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct QOpenOrders {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub symbol: Option<String>,
+}
+
+impl ApiQuery for QOpenOrders {
+    type Response = Vec<QueriedOrder>;
+    const METHOD: &'static str = "openOrders.status";
+    const TYPE: QueryType = QueryType::AuthWithoutApiKey;
+
+    fn weight(&self) -> u32 {
+        if self.symbol.is_some() { 6 } else { 80 }
+    }
+}
+
+impl RestQuery for QOpenOrders {
+    const PATH: &'static str = "/api/v3/openOrders";
+}
+
+/// 所有订单, 按时间查询时 `start_time` 和 `end_time` 间隔不能超过 24 小时
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct QAllOrders {
+    pub symbol: String,
+    /// 返回大于等于此ID的订单
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub order_id: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start_time: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end_time: Option<i64>,
+    /// 默认 500, 最大 1000
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<u64>,
+}
+
+impl ApiQuery for QAllOrders {
+    type Response = Vec<QueriedOrder>;
+    const METHOD: &'static str = "allOrders";
+    const TYPE: QueryType = QueryType::AuthWithoutApiKey;
+
+    fn weight(&self) -> u32 {
+        20
+    }
+}
+
+impl RestQuery for QAllOrders {
+    const PATH: &'static str = "/api/v3/allOrders";
+}
+
+/// 账户成交历史, 按时间查询时 `start_time` 和 `end_time` 间隔不能超过 24 小时
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct QMyTrades {
+    pub symbol: String,
+    /// 只返回此订单的成交, 不能和时间同时使用
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub order_id: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start_time: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end_time: Option<i64>,
+    /// 返回大于等于此ID的成交
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from_id: Option<i64>,
+    /// 默认 500, 最大 1000
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<u64>,
+}
+
+impl ApiQuery for QMyTrades {
+    type Response = Vec<AccountTrade>;
+    const METHOD: &'static str = "myTrades";
+    const TYPE: QueryType = QueryType::AuthWithoutApiKey;
+
+    fn weight(&self) -> u32 {
+        if self.order_id.is_some() { 5 } else { 20 }
+    }
+}
+
+impl RestQuery for QMyTrades {
+    const PATH: &'static str = "/api/v3/myTrades";
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountTrade {
+    pub symbol: String,
+    /// 成交ID
+    pub id: i64,
+    pub order_id: i64,
+    #[serde(default)]
+    pub order_list_id: i64,
+    pub price: Decimal,
+    pub qty: Decimal,
+    pub quote_qty: Decimal,
+    /// 手续费金额
+    pub commission: Decimal,
+    /// 手续费的币种
+    pub commission_asset: String,
+    pub time: i64,
+    pub is_buyer: bool,
+    pub is_maker: bool,
+    pub is_best_match: bool,
+}
+
+/// 合约当前挂单, 不指定交易对时返回所有交易对的挂单
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct QFutureOpenOrders {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub symbol: Option<String>,
+}
+
+impl ApiQuery for QFutureOpenOrders {
+    type Response = Vec<FutureQueriedOrder>;
+    const METHOD: &'static str = "openOrders.status";
+    const TYPE: QueryType = QueryType::AuthWithoutApiKey;
+
+    fn weight(&self) -> u32 {
+        if self.symbol.is_some() { 1 } else { 40 }
+    }
+}
+
+impl RestQuery for QFutureOpenOrders {
+    const PATH: &'static str = "/fapi/v1/openOrders";
+}
+
+/// 合约所有订单, `start_time` 和 `end_time` 间隔不能超过 7 天
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct QFutureAllOrders {
+    pub symbol: String,
+    /// 返回大于等于此ID的订单
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub order_id: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start_time: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end_time: Option<i64>,
+    /// 默认 500, 最大 1000
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<u64>,
+}
+
+impl ApiQuery for QFutureAllOrders {
+    type Response = Vec<FutureQueriedOrder>;
+    const METHOD: &'static str = "allOrders";
+    const TYPE: QueryType = QueryType::AuthWithoutApiKey;
+
+    fn weight(&self) -> u32 {
+        5
+    }
+}
+
+impl RestQuery for QFutureAllOrders {
+    const PATH: &'static str = "/fapi/v1/allOrders";
+}
+
+/// 合约账户成交历史, `start_time` 和 `end_time` 间隔不能超过 7 天
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct QFutureUserTrades {
+    pub symbol: String,
+    /// 只返回此订单的成交
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub order_id: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start_time: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end_time: Option<i64>,
+    /// 返回大于等于此ID的成交
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from_id: Option<i64>,
+    /// 默认 500, 最大 1000
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<u64>,
+}
+
+impl ApiQuery for QFutureUserTrades {
+    type Response = Vec<FutureAccountTrade>;
+    const METHOD: &'static str = "userTrades";
+    const TYPE: QueryType = QueryType::AuthWithoutApiKey;
+
+    fn weight(&self) -> u32 {
+        5
+    }
+}
+
+impl RestQuery for QFutureUserTrades {
+    const PATH: &'static str = "/fapi/v1/userTrades";
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FutureAccountTrade {
+    pub symbol: String,
+    /// 成交ID
+    pub id: i64,
+    pub order_id: i64,
+    pub side: OrderSide,
+    pub position_side: PositionSide,
+    pub price: Decimal,
+    pub qty: Decimal,
+    pub quote_qty: Decimal,
+    /// 实现盈亏
+    pub realized_pnl: Decimal,
+    /// 手续费金额
+    pub commission: Decimal,
+    /// 手续费的币种
+    pub commission_asset: String,
+    pub time: i64,
+    pub buyer: bool,
+    pub maker: bool,
+}
+
+/// 撤销交易对的所有挂单, 包括订单列表
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct CancelOpenOrders {
+    pub symbol: String,
+}
+
+impl ApiQuery for CancelOpenOrders {
+    type Response = Vec<CanceledOrder>;
+    const METHOD: &'static str = "openOrders.cancelAll";
+    const TYPE: QueryType = QueryType::AuthWithoutApiKey;
+}
+
+impl RestQuery for CancelOpenOrders {
+    const PATH: &'static str = "/api/v3/openOrders";
+    const VERB: Verb = Verb::Delete;
+}
+
+/// 撤销合约交易对的所有挂单
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct FutureCancelOpenOrders {
+    pub symbol: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FutureCancelOpenOrdersResult {
+    pub code: i64,
+    pub msg: String,
+}
+
+impl ApiQuery for FutureCancelOpenOrders {
+    type Response = FutureCancelOpenOrdersResult;
+    const METHOD: &'static str = "openOrders.cancelAll";
+    const TYPE: QueryType = QueryType::AuthWithoutApiKey;
+}
+
+impl RestQuery for FutureCancelOpenOrders {
+    const PATH: &'static str = "/fapi/v1/allOpenOrders";
+    const VERB: Verb = Verb::Delete;
+}
+
+/// 撤销的订单或订单列表(OCO 等)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum CanceledOrder {
+    Order(OrderResult),
+    OrderList(serde_json::Value),
+}
+
+/// 撤单失败时是否继续下新单
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum CancelReplaceMode {
+    #[default]
+    StopOnFailure,
+    AllowFailure,
+}
+
+/// 超过下单频率限制时的处理方式
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum OrderRateLimitExceededMode {
+    /// 不撤单也不下单
+    DoNothing,
+    /// 只撤单
+    CancelOnly,
+}
+
+/// 撤销一个订单并下一个新订单, `cancel_order_id` 和 `cancel_orig_client_order_id` 至少设置一个
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct CancelReplaceOrder {
+    pub cancel_replace_mode: CancelReplaceMode,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cancel_order_id: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cancel_orig_client_order_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cancel_new_client_order_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cancel_restrictions: Option<CancelRestrictions>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub order_rate_limit_exceeded_mode: Option<OrderRateLimitExceededMode>,
+    /// 新订单
+    #[serde(flatten)]
+    pub order: OrderSpec,
+}
+
+impl ApiQuery for CancelReplaceOrder {
+    type Response = CancelReplaceResult;
+    const METHOD: &'static str = "order.cancelReplace";
+    const TYPE: QueryType = QueryType::AuthWithoutApiKey;
+    const IS_ORDER: bool = true;
+}
+
+impl RestQuery for CancelReplaceOrder {
+    const PATH: &'static str = "/api/v3/order/cancelReplace";
+    const VERB: Verb = Verb::Post;
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum CancelReplaceStatus {
+    Success,
+    Failure,
+    NotAttempted,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum CancelReplacePart<T> {
+    Ok(T),
+    Err(BinanceError),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CancelReplaceResult {
+    pub cancel_result: CancelReplaceStatus,
+    pub new_order_result: CancelReplaceStatus,
+    pub cancel_response: Option<CancelReplacePart<OrderResult>>,
+    pub new_order_response: Option<CancelReplacePart<CreateOrderResponse>>,
+}
+
+impl CancelReplaceResult {
+    /// 撤单或下单失败时错误的 `data` 中带有两步各自的结果
+    pub fn from_error(error: &BinanceError) -> Option<Self> {
+        match error.error_code() {
+            ErrorCode::CancelReplacePartiallyFailed | ErrorCode::CancelReplaceFailed => {
+                serde_json::from_value(error.data.clone()?).ok()
+            }
+            _ => None,
+        }
+    }
+}
+
+/// 修改合约限价单的价格和数量, 修改后订单在队列中重新排队
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct FutureModifyOrder {
+    pub symbol: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub order_id: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub orig_client_order_id: Option<String>,
+    pub side: OrderSide,
+    pub quantity: Decimal,
+    /// 与 `price_match` 二选一
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub price: Option<Decimal>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub price_match: Option<String>,
+}
+
+impl ApiQuery for FutureModifyOrder {
+    type Response = FutureOrderResult;
+    const METHOD: &'static str = "order.modify";
+    const TYPE: QueryType = QueryType::AuthWithoutApiKey;
+    const IS_ORDER: bool = true;
+}
+
+impl RestQuery for FutureModifyOrder {
+    const PATH: &'static str = "/fapi/v1/order";
+    const VERB: Verb = Verb::Put;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn future_queried_order_with_gtd() {
+        let order: FutureQueriedOrder = serde_json::from_str(
+            r#"{"avgPrice":"0.00000","clientOrderId":"abc","cumQuote":"0","executedQty":"0",
+            "orderId":1917641,"origQty":"0.40","origType":"LIMIT","price":"90000","reduceOnly":false,
+            "side":"BUY","positionSide":"BOTH","status":"NEW","stopPrice":"0","closePosition":false,
+            "symbol":"BTCUSDT","time":1579276756075,"timeInForce":"GTD","type":"LIMIT",
+            "activatePrice":"9020","priceRate":"0.3","updateTime":1579276756075,
+            "workingType":"CONTRACT_PRICE","priceProtect":false,"priceMatch":"NONE",
+            "selfTradePreventionMode":"NONE","goodTillDate":1579363200000}"#,
+        )
+        .unwrap();
+        assert_eq!(order.time_in_force, TimeInForce::GTD);
+        assert_eq!(order.good_till_date, 1579363200000);
+        assert_eq!(order.status, OrderStatus::New);
+    }
+
+    #[test]
+    fn future_modify_order_with_price_match() {
+        let query = FutureModifyOrder {
+            symbol: "BTCUSDT".into(),
+            order_id: Some(1),
+            quantity: Decimal::ONE,
+            price_match: Some("QUEUE".into()),
+            ..Default::default()
+        };
+        let value = serde_json::to_value(&query).unwrap();
+        assert!(value.get("price").is_none());
+        assert_eq!(value["priceMatch"], "QUEUE");
+    }
+}
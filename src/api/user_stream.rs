@@ -1,5 +1,7 @@
 use serde::{Deserialize, Serialize};
 
+use crate::rest::{RestQuery, Verb};
+
 use super::{ApiQuery, EmptyResponse, QueryType};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -18,6 +20,15 @@ impl ApiQuery for StartUserStream {
     type Response = ListenKey;
     const METHOD: &'static str = "userDataStream.start";
     const TYPE: QueryType = QueryType::None;
+
+    fn weight(&self) -> u32 {
+        2
+    }
+}
+
+impl RestQuery for StartUserStream {
+    const PATH: &'static str = "/api/v3/userDataStream";
+    const VERB: Verb = Verb::Post;
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -31,6 +42,15 @@ impl ApiQuery for PingUserStream {
     type Response = EmptyResponse;
     const METHOD: &'static str = "userDataStream.ping";
     const TYPE: QueryType = QueryType::None;
+
+    fn weight(&self) -> u32 {
+        2
+    }
+}
+
+impl RestQuery for PingUserStream {
+    const PATH: &'static str = "/api/v3/userDataStream";
+    const VERB: Verb = Verb::Put;
 }
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -43,4 +63,71 @@ impl ApiQuery for CloseUserStream {
     type Response = EmptyResponse;
     const METHOD: &'static str = "userDataStream.close";
     const TYPE: QueryType = QueryType::None;
+
+    fn weight(&self) -> u32 {
+        2
+    }
+}
+
+impl RestQuery for CloseUserStream {
+    const PATH: &'static str = "/api/v3/userDataStream";
+    const VERB: Verb = Verb::Delete;
+}
+
+/// 在已登录的会话上订阅用户数据, 不需要 listenKey
+///
+/// 事件通过 [`EventFrame`](super::EventFrame) 推送到同一连接
+#[derive(Debug, Clone, Deserialize)]
+pub struct SubscribeUserStream;
+
+empty_serde!(SubscribeUserStream);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserStreamSubscription {
+    pub subscription_id: i64,
+}
+
+impl ApiQuery for SubscribeUserStream {
+    type Response = UserStreamSubscription;
+    const METHOD: &'static str = "userDataStream.subscribe";
+    const TYPE: QueryType = QueryType::None;
+
+    fn weight(&self) -> u32 {
+        2
+    }
+}
+
+/// 未登录时使用签名订阅用户数据, 适用于 HMAC 和 RSA key
+#[derive(Debug, Clone, Deserialize)]
+pub struct SubscribeUserStreamSignature;
+
+empty_serde!(SubscribeUserStreamSignature);
+
+impl ApiQuery for SubscribeUserStreamSignature {
+    type Response = UserStreamSubscription;
+    const METHOD: &'static str = "userDataStream.subscribe.signature";
+    const TYPE: QueryType = QueryType::Authorized;
+
+    fn weight(&self) -> u32 {
+        2
+    }
+}
+
+/// 取消订阅, 不指定 `subscription_id` 时取消当前连接上的所有订阅
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct UnsubscribeUserStream {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subscription_id: Option<i64>,
+}
+
+impl ApiQuery for UnsubscribeUserStream {
+    type Response = EmptyResponse;
+    const METHOD: &'static str = "userDataStream.unsubscribe";
+    const TYPE: QueryType = QueryType::None;
+
+    fn weight(&self) -> u32 {
+        2
+    }
 }
@@ -0,0 +1,156 @@
+use std::{sync::Arc, time::Duration};
+
+use tracing::warn;
+
+use crate::{
+    api::{ApiResult, AutoReconnectClient, Client, ClientError},
+    millis_ts,
+    rest::{RestClient, RestQuery},
+};
+
+/// 发送请求的方式, 由 WebSocket API 客户端和 [`RestClient`] 实现
+///
+/// 请求需要同时实现 [`RestQuery`], 策略代码只依赖这个 trait 就可以通过配置切换传输方式
+pub trait Executor {
+    fn execute<P: RestQuery>(&mut self, param: P) -> ApiResult<P::Response>;
+}
+
+impl Executor for Client {
+    fn execute<P: RestQuery>(&mut self, param: P) -> ApiResult<P::Response> {
+        self.query(param)
+    }
+}
+
+impl Executor for AutoReconnectClient {
+    fn execute<P: RestQuery>(&mut self, param: P) -> ApiResult<P::Response> {
+        self.query(param)
+    }
+}
+
+impl Executor for Arc<AutoReconnectClient> {
+    fn execute<P: RestQuery>(&mut self, param: P) -> ApiResult<P::Response> {
+        self.query(param)
+    }
+}
+
+impl Executor for RestClient {
+    fn execute<P: RestQuery>(&mut self, param: P) -> ApiResult<P::Response> {
+        self.query(param)
+    }
+}
+
+impl Executor for Arc<RestClient> {
+    fn execute<P: RestQuery>(&mut self, param: P) -> ApiResult<P::Response> {
+        self.query(param)
+    }
+}
+
+/// 根据配置选择的传输方式
+pub enum Transport {
+    Ws(AutoReconnectClient),
+    Rest(RestClient),
+}
+
+impl Executor for Transport {
+    fn execute<P: RestQuery>(&mut self, param: P) -> ApiResult<P::Response> {
+        match self {
+            Transport::Ws(client) => client.query(param),
+            Transport::Rest(client) => client.query(param),
+        }
+    }
+}
+
+/// 主通道连接出错或超时后改用备用通道, 如 WebSocket API 不可用时改用 REST
+///
+/// 出错后的 `cooldown` 内直接使用备用通道. 下单请求出错时可能已经发送成功,
+/// 不会自动改用备用通道重发, 而是直接返回错误
+pub struct Fallback<A, B> {
+    primary: A,
+    secondary: B,
+    cooldown: i64,
+    /// 主通道恢复使用的时间(ms)
+    degraded_until: i64,
+}
+
+impl<A: Executor, B: Executor> Fallback<A, B> {
+    pub fn new(primary: A, secondary: B, cooldown: Duration) -> Self {
+        Self {
+            primary,
+            secondary,
+            cooldown: cooldown.as_millis() as i64,
+            degraded_until: 0,
+        }
+    }
+
+    /// 主通道是否处于降级状态
+    pub fn is_degraded(&self) -> bool {
+        self.degraded_until > millis_ts()
+    }
+
+    pub fn primary(&mut self) -> &mut A {
+        &mut self.primary
+    }
+
+    pub fn secondary(&mut self) -> &mut B {
+        &mut self.secondary
+    }
+}
+
+impl<A: Executor, B: Executor> Executor for Fallback<A, B> {
+    fn execute<P: RestQuery>(&mut self, param: P) -> ApiResult<P::Response> {
+        if self.is_degraded() {
+            return self.secondary.execute(param);
+        }
+        let retry = (!P::IS_ORDER).then(|| param.clone());
+        match self.primary.execute(param) {
+            Err(e @ (ClientError::WsError(_) | ClientError::Timeout)) => {
+                warn!("{} failed on primary transport: {e}", P::METHOD);
+                self.degraded_until = millis_ts() + self.cooldown;
+                match retry {
+                    Some(param) => self.secondary.execute(param),
+                    None => Err(e),
+                }
+            }
+            resp => resp,
+        }
+    }
+}
+
+/// 收到限频错误时等待到 `retryAfter` 后重试, 连接错误等可重试错误也会重试, 不是下单的请求
+/// 在执行状态未知时(超时, 连接断开)也会重试,
+/// 两者合计最多重试 `max_retries` 次, 之后返回最后的错误
+pub(crate) fn execute_with_retry<E: Executor, P: RestQuery>(
+    executor: &mut E,
+    param: P,
+    max_retries: usize,
+) -> ApiResult<P::Response> {
+    let mut retries = 0;
+    loop {
+        match executor.execute(param.clone()) {
+            Ok(resp) => return Ok(resp),
+            Err(e) if e.is_rate_limit() && retries < max_retries => {
+                retries += 1;
+                let wait = match &e {
+                    ClientError::RateLimited(wait) => *wait,
+                    _ => match e.api_error().and_then(|e| e.retry_after()) {
+                        Some(until) => Duration::from_millis((until - millis_ts()).max(0) as u64),
+                        // 没有 retryAfter 时指数退避
+                        None => Duration::from_secs(1 << retries.min(6)),
+                    },
+                };
+                warn!("{} rate limited, wait {wait:?}, retry {retries}", P::METHOD);
+                std::thread::sleep(wait);
+            }
+            // 查询可以安全重试, 执行状态未知的下单和撤单交给调用方处理
+            Err(e)
+                if (e.is_retryable() || (!P::IS_ORDER && e.is_unknown_outcome()))
+                    && retries < max_retries =>
+            {
+                retries += 1;
+                warn!("{} failed {e}, retry {retries}", P::METHOD);
+                std::thread::sleep(Duration::from_millis(500 * retries as u64));
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
@@ -0,0 +1,539 @@
+use rust_decimal::Decimal;
+
+use crate::{
+    api::market::{AggTrade, TradeRecord},
+    realtime_market::{AggTradeEvent, Interval, KData, TradeEvent},
+};
+
+/// 可以合成K线的成交
+pub trait TradeTick {
+    /// 成交时间(ms)
+    fn trade_time(&self) -> i64;
+    fn price(&self) -> Decimal;
+    fn qty(&self) -> Decimal;
+    /// 成交额
+    fn quote_qty(&self) -> Decimal {
+        self.price() * self.qty()
+    }
+    /// 买方是否为挂单方, 否则为主动买入
+    fn is_buyer_maker(&self) -> bool;
+    /// 包含的第一笔和最后一笔成交ID
+    fn trade_ids(&self) -> (i64, i64);
+}
+
+impl TradeTick for TradeRecord {
+    fn trade_time(&self) -> i64 {
+        self.time
+    }
+
+    fn price(&self) -> Decimal {
+        self.price
+    }
+
+    fn qty(&self) -> Decimal {
+        self.qty
+    }
+
+    fn quote_qty(&self) -> Decimal {
+        self.quote_qty
+    }
+
+    fn is_buyer_maker(&self) -> bool {
+        self.is_buyer_maker
+    }
+
+    fn trade_ids(&self) -> (i64, i64) {
+        (self.id as i64, self.id as i64)
+    }
+}
+
+impl TradeTick for AggTrade {
+    fn trade_time(&self) -> i64 {
+        self.time
+    }
+
+    fn price(&self) -> Decimal {
+        self.price
+    }
+
+    fn qty(&self) -> Decimal {
+        self.qty
+    }
+
+    fn is_buyer_maker(&self) -> bool {
+        self.is_maker
+    }
+
+    fn trade_ids(&self) -> (i64, i64) {
+        (self.first_trade_id, self.last_trade_id)
+    }
+}
+
+impl TradeTick for AggTradeEvent {
+    fn trade_time(&self) -> i64 {
+        self.data.trade_time()
+    }
+
+    fn price(&self) -> Decimal {
+        self.data.price()
+    }
+
+    fn qty(&self) -> Decimal {
+        self.data.qty()
+    }
+
+    fn is_buyer_maker(&self) -> bool {
+        self.data.is_buyer_maker()
+    }
+
+    fn trade_ids(&self) -> (i64, i64) {
+        self.data.trade_ids()
+    }
+}
+
+impl TradeTick for TradeEvent {
+    fn trade_time(&self) -> i64 {
+        self.data.time
+    }
+
+    fn price(&self) -> Decimal {
+        self.data.price
+    }
+
+    fn qty(&self) -> Decimal {
+        self.data.qty
+    }
+
+    fn is_buyer_maker(&self) -> bool {
+        self.data.is_buyer_maker
+    }
+
+    fn trade_ids(&self) -> (i64, i64) {
+        (self.data.trade_id, self.data.trade_id)
+    }
+}
+
+/// 合成K线的方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BarKind {
+    /// 按标准周期
+    Time(Interval),
+    /// 按自定义周期(ms), 从 1970-01-01 起按整数倍对齐, 如 2m, 10m
+    Millis(i64),
+    /// 每 n 笔成交
+    Tick(u64),
+    /// 成交量达到阈值
+    Volume(Decimal),
+    /// 成交额达到阈值
+    QuoteVolume(Decimal),
+}
+
+impl BarKind {
+    /// `ts` 所在时间K线的开盘和收盘时间, 非时间K线返回 `None`
+    fn bucket(&self, ts: i64) -> Option<(i64, i64)> {
+        match *self {
+            Self::Time(interval) => Some((interval.align(ts), interval.next_open(ts) - 1)),
+            Self::Millis(step) => {
+                let open = ts - ts.rem_euclid(step.max(1));
+                Some((open, open + step.max(1) - 1))
+            }
+            _ => None,
+        }
+    }
+
+    /// 非时间K线是否达到阈值
+    fn is_full(&self, bar: &KData) -> bool {
+        match *self {
+            Self::Tick(n) => bar.trade_num >= n,
+            Self::Volume(v) => bar.volume >= v,
+            Self::QuoteVolume(q) => bar.qty >= q,
+            Self::Time(_) | Self::Millis(_) => false,
+        }
+    }
+
+    /// 输出K线对应的标准周期, 自定义周期和非时间K线返回 `None`
+    pub fn interval(&self) -> Option<Interval> {
+        match *self {
+            Self::Time(interval) => Some(interval),
+            Self::Millis(step) => Interval::try_from(time::Duration::milliseconds(step)).ok(),
+            _ => None,
+        }
+    }
+}
+
+/// 用逐笔成交或更小周期的K线合成K线
+///
+/// 时间K线没有成交的周期不会生成K线. 非时间K线的开盘和收盘时间为第一笔和最后一笔成交的时间,
+/// 一笔成交不会拆分到两根K线中, 所以成交量可能超过阈值.
+/// 输出K线的 `interval` 只在 [`BarKind::interval`] 有值时有意义
+pub struct KlineAggregator {
+    symbol: String,
+    kind: BarKind,
+    /// 当前时间K线的开盘和收盘时间
+    bucket: Option<(i64, i64)>,
+    /// 当前K线中已完结部分的合计
+    closed: Option<KData>,
+    /// 未完结的输入K线, 收到同一根的更新时替换
+    partial: Option<KData>,
+    /// 上一根完结K线的收盘时间, 更早的输入会被丢弃
+    last_close: Option<i64>,
+}
+
+impl KlineAggregator {
+    pub fn new(symbol: &str, kind: BarKind) -> Self {
+        Self {
+            symbol: symbol.to_string(),
+            kind,
+            bucket: None,
+            closed: None,
+            partial: None,
+            last_close: None,
+        }
+    }
+
+    pub fn kind(&self) -> BarKind {
+        self.kind
+    }
+
+    /// 加入一笔成交, 返回因此完结的K线
+    ///
+    /// 成交需要按时间顺序加入, 早于上一根完结K线的成交会被丢弃.
+    /// 同一毫秒可能有多笔成交, 所以时间K线在收到之后周期的成交或
+    /// [`KlineAggregator::close_expired`] 时才完结
+    pub fn push_trade<T: TradeTick>(&mut self, trade: &T) -> Vec<KData> {
+        let (first_id, last_id) = trade.trade_ids();
+        let (price, qty, quote_qty) = (trade.price(), trade.qty(), trade.quote_qty());
+        let (take_volume, take_qty) = match trade.is_buyer_maker() {
+            true => (Decimal::ZERO, Decimal::ZERO),
+            false => (qty, quote_qty),
+        };
+        let piece = KData {
+            open_time: trade.trade_time(),
+            close_time: trade.trade_time(),
+            symbol: Default::default(),
+            interval: Default::default(),
+            first_id,
+            last_id,
+            open: price,
+            close: price,
+            high: price,
+            low: price,
+            volume: qty,
+            trade_num: (last_id - first_id + 1).max(1) as u64,
+            is_end: true,
+            qty: quote_qty,
+            take_volume,
+            take_qty,
+            __ignore: Default::default(),
+        };
+        self.push(piece, true)
+    }
+
+    /// 加入更小周期的K线, 返回因此完结的K线
+    ///
+    /// 未完结的K线(`is_end` 为 `false`)可以重复加入, 以最新的一次为准.
+    /// 非时间K线只计入已完结的K线
+    pub fn push_kline(&mut self, kline: &KData) -> Vec<KData> {
+        self.push(kline.clone(), false)
+    }
+
+    /// 当前未完结的K线
+    pub fn current(&self) -> Option<KData> {
+        let mut bar = self.closed.clone();
+        if let Some(partial) = &self.partial {
+            merge(&mut bar, partial);
+        }
+        bar.map(|bar| self.finish(bar, false))
+    }
+
+    /// 时间K线的收盘时间早于 `now` 时完结, 用于没有新成交时按时收线
+    pub fn close_expired(&mut self, now: i64) -> Option<KData> {
+        let (_, close_time) = self.bucket?;
+        (close_time < now).then(|| self.take()).flatten()
+    }
+
+    /// 输入早于上一根完结的K线
+    fn is_stale(&self, piece: &KData, is_trade: bool) -> bool {
+        let Some(last_close) = self.last_close else {
+            return false;
+        };
+        match self.kind.bucket(piece.open_time) {
+            Some(_) => piece.open_time <= last_close,
+            // 非时间K线收盘时间为最后一笔成交的时间, 同一毫秒的成交仍然有效
+            None if is_trade => piece.open_time < last_close,
+            None => piece.open_time <= last_close,
+        }
+    }
+
+    fn push(&mut self, piece: KData, is_trade: bool) -> Vec<KData> {
+        let mut done = vec![];
+        if self.is_stale(&piece, is_trade) {
+            tracing::debug!("drop stale {} piece at {}", self.symbol, piece.open_time);
+            return done;
+        }
+        match self.kind.bucket(piece.open_time) {
+            Some(bucket) => {
+                let (_, close_time) = match self.bucket {
+                    Some(current) if bucket.0 <= current.0 => current,
+                    current => {
+                        if current.is_some() {
+                            done.extend(self.take());
+                        }
+                        self.bucket = Some(bucket);
+                        bucket
+                    }
+                };
+                let is_end = !is_trade && piece.is_end && piece.close_time >= close_time;
+                self.add(piece);
+                if is_end {
+                    done.extend(self.take());
+                }
+            }
+            None => {
+                if !piece.is_end {
+                    return done;
+                }
+                self.add(piece);
+                if self
+                    .closed
+                    .as_ref()
+                    .is_some_and(|bar| self.kind.is_full(bar))
+                {
+                    done.extend(self.take());
+                }
+            }
+        }
+        done
+    }
+
+    fn add(&mut self, piece: KData) {
+        // 同一根输入K线的新数据替换之前未完结的部分
+        if let Some(partial) = self.partial.take()
+            && partial.open_time != piece.open_time
+        {
+            merge(&mut self.closed, &partial);
+        }
+        if piece.is_end {
+            merge(&mut self.closed, &piece);
+        } else {
+            self.partial = Some(piece);
+        }
+    }
+
+    fn take(&mut self) -> Option<KData> {
+        let mut bar = self.closed.take();
+        if let Some(partial) = self.partial.take() {
+            merge(&mut bar, &partial);
+        }
+        let bar = bar.map(|bar| self.finish(bar, true));
+        if let Some(bar) = &bar {
+            self.last_close = Some(bar.close_time);
+        }
+        self.bucket = None;
+        bar
+    }
+
+    /// 设置输出K线的交易对, 周期和时间
+    fn finish(&self, mut bar: KData, is_end: bool) -> KData {
+        bar.symbol.clone_from(&self.symbol);
+        if let Some(interval) = self.kind.interval() {
+            bar.interval = interval;
+        }
+        if let Some((open_time, close_time)) = self.bucket {
+            bar.open_time = open_time;
+            bar.close_time = close_time;
+        }
+        bar.is_end = is_end;
+        bar
+    }
+}
+
+/// 把 `piece` 合并到 `bar` 之后
+fn merge(bar: &mut Option<KData>, piece: &KData) {
+    let Some(bar) = bar else {
+        *bar = Some(piece.clone());
+        return;
+    };
+    bar.close_time = bar.close_time.max(piece.close_time);
+    if bar.first_id < 0 {
+        bar.first_id = piece.first_id;
+    }
+    if piece.last_id >= 0 {
+        bar.last_id = piece.last_id;
+    }
+    bar.close = piece.close;
+    bar.high = bar.high.max(piece.high);
+    bar.low = bar.low.min(piece.low);
+    bar.volume += piece.volume;
+    bar.trade_num += piece.trade_num;
+    bar.qty += piece.qty;
+    bar.take_volume += piece.take_volume;
+    bar.take_qty += piece.take_qty;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Trade {
+        id: i64,
+        time: i64,
+        price: i64,
+        qty: i64,
+        maker: bool,
+    }
+
+    impl TradeTick for Trade {
+        fn trade_time(&self) -> i64 {
+            self.time
+        }
+
+        fn price(&self) -> Decimal {
+            self.price.into()
+        }
+
+        fn qty(&self) -> Decimal {
+            self.qty.into()
+        }
+
+        fn is_buyer_maker(&self) -> bool {
+            self.maker
+        }
+
+        fn trade_ids(&self) -> (i64, i64) {
+            (self.id, self.id)
+        }
+    }
+
+    fn trade(id: i64, time: i64, price: i64, qty: i64) -> Trade {
+        Trade {
+            id,
+            time,
+            price,
+            qty,
+            maker: id % 2 == 0,
+        }
+    }
+
+    fn kline(open_time: i64, close: i64, volume: i64, is_end: bool) -> KData {
+        KData {
+            open_time,
+            close_time: open_time + 59_999,
+            interval: Interval::Min1,
+            first_id: open_time / 1000,
+            last_id: open_time / 1000 + 1,
+            open: close.into(),
+            close: close.into(),
+            high: close.into(),
+            low: close.into(),
+            volume: volume.into(),
+            trade_num: 2,
+            is_end,
+            qty: (close * volume).into(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn time_bar_closes_on_next_bucket() {
+        let mut agg = KlineAggregator::new("BTCUSDT", BarKind::Time(Interval::Min1));
+        assert!(agg.push_trade(&trade(1, 1_000, 10, 1)).is_empty());
+        assert!(agg.push_trade(&trade(2, 30_000, 12, 2)).is_empty());
+        // 周期最后一毫秒的成交不会立即收线, 同一毫秒的成交计入同一根
+        assert!(agg.push_trade(&trade(3, 59_999, 9, 1)).is_empty());
+        assert!(agg.push_trade(&trade(4, 59_999, 11, 1)).is_empty());
+        let done = agg.push_trade(&trade(5, 60_000, 13, 1));
+        assert_eq!(done.len(), 1);
+        let bar = &done[0];
+        assert_eq!((bar.open_time, bar.close_time), (0, 59_999));
+        assert_eq!(bar.interval, Interval::Min1);
+        assert_eq!(bar.symbol, "BTCUSDT");
+        assert_eq!((bar.first_id, bar.last_id), (1, 4));
+        assert_eq!(bar.open, Decimal::from(10));
+        assert_eq!(bar.close, Decimal::from(11));
+        assert_eq!(bar.high, Decimal::from(12));
+        assert_eq!(bar.low, Decimal::from(9));
+        assert_eq!(bar.volume, Decimal::from(5));
+        assert_eq!(bar.trade_num, 4);
+        // 1, 3 为主动买入
+        assert_eq!(bar.take_volume, Decimal::from(2));
+        assert_eq!(bar.take_qty, Decimal::from(19));
+        assert!(bar.is_end);
+        // 已完结周期的迟到成交被丢弃
+        assert!(agg.push_trade(&trade(6, 59_999, 1, 100)).is_empty());
+        let current = agg.current().unwrap();
+        assert_eq!(current.open_time, 60_000);
+        assert_eq!(current.volume, Decimal::ONE);
+        assert!(!current.is_end);
+    }
+
+    #[test]
+    fn close_expired_drops_late_trades() {
+        let mut agg = KlineAggregator::new("BTCUSDT", BarKind::Millis(120_000));
+        assert_eq!(BarKind::Millis(120_000).interval(), None);
+        agg.push_trade(&trade(1, 100_000, 10, 1));
+        assert!(agg.close_expired(119_999).is_none());
+        let bar = agg.close_expired(120_000).unwrap();
+        assert_eq!((bar.open_time, bar.close_time), (0, 119_999));
+        assert!(agg.push_trade(&trade(2, 119_999, 10, 1)).is_empty());
+        assert!(agg.current().is_none());
+        assert!(agg.close_expired(1_000_000).is_none());
+    }
+
+    #[test]
+    fn volume_and_tick_bars() {
+        let mut agg = KlineAggregator::new("BTCUSDT", BarKind::Volume(3.into()));
+        assert!(agg.push_trade(&trade(1, 1_000, 10, 2)).is_empty());
+        let done = agg.push_trade(&trade(2, 2_000, 11, 2));
+        assert_eq!(done.len(), 1);
+        // 一笔成交不会拆分, 成交量可以超过阈值
+        assert_eq!(done[0].volume, Decimal::from(4));
+        assert_eq!((done[0].open_time, done[0].close_time), (1_000, 2_000));
+        // 同一毫秒的成交开始新的K线, 更早的成交被丢弃
+        assert!(agg.push_trade(&trade(3, 1_999, 12, 5)).is_empty());
+        assert!(agg.push_trade(&trade(4, 2_000, 12, 1)).is_empty());
+        assert_eq!(agg.current().unwrap().volume, Decimal::ONE);
+
+        let mut agg = KlineAggregator::new("BTCUSDT", BarKind::Tick(2));
+        assert!(agg.push_trade(&trade(1, 1_000, 10, 1)).is_empty());
+        let done = agg.push_trade(&trade(2, 1_000, 11, 1));
+        assert_eq!(done[0].trade_num, 2);
+        assert_eq!(done[0].close, Decimal::from(11));
+        assert!(agg.current().is_none());
+    }
+
+    #[test]
+    fn partial_klines_replaced() {
+        let mut agg = KlineAggregator::new("BTCUSDT", BarKind::Time(Interval::Min3));
+        assert!(agg.push_kline(&kline(0, 10, 1, true)).is_empty());
+        assert!(agg.push_kline(&kline(60_000, 11, 1, false)).is_empty());
+        // 同一根输入K线的更新替换之前的数据
+        assert!(agg.push_kline(&kline(60_000, 12, 2, false)).is_empty());
+        assert_eq!(agg.current().unwrap().volume, Decimal::from(3));
+        assert!(agg.push_kline(&kline(60_000, 12, 3, true)).is_empty());
+        assert!(agg.push_kline(&kline(120_000, 9, 1, false)).is_empty());
+        // 最后一根输入K线完结时收线
+        let done = agg.push_kline(&kline(120_000, 9, 2, true));
+        assert_eq!(done.len(), 1);
+        let bar = &done[0];
+        assert_eq!((bar.open_time, bar.close_time), (0, 179_999));
+        assert_eq!(bar.interval, Interval::Min3);
+        assert_eq!(bar.volume, Decimal::from(6));
+        assert_eq!(bar.trade_num, 6);
+        assert_eq!(bar.close, Decimal::from(9));
+        assert_eq!(bar.high, Decimal::from(12));
+        // 已完结周期的重复推送被丢弃
+        assert!(agg.push_kline(&kline(120_000, 9, 2, true)).is_empty());
+        assert!(agg.current().is_none());
+    }
+
+    #[test]
+    fn volume_bar_ignores_partial_klines() {
+        let mut agg = KlineAggregator::new("BTCUSDT", BarKind::QuoteVolume(100.into()));
+        assert!(agg.push_kline(&kline(0, 10, 20, false)).is_empty());
+        assert!(agg.current().is_none());
+        let done = agg.push_kline(&kline(0, 10, 20, true));
+        assert_eq!(done[0].qty, Decimal::from(200));
+        assert!(agg.push_kline(&kline(0, 10, 20, true)).is_empty());
+    }
+}
@@ -0,0 +1,174 @@
+use tracing::{debug, warn};
+
+use crate::{
+    api::{ClientError, market, market::Kline},
+    executor::{Executor, execute_with_retry},
+    millis_ts,
+    realtime_market::Interval,
+    rest::{self, RestQuery},
+};
+
+/// 分页获取K线的请求, 由现货 [`market::QKline`] 和合约 [`rest::QKline`] 实现
+pub trait KlineQuery: RestQuery<Response = Vec<Kline>> {
+    /// 单页最大数量
+    const MAX_LIMIT: i64;
+
+    fn page(symbol: &str, interval: Interval, start: i64, end: i64, limit: i64) -> Self;
+}
+
+impl KlineQuery for market::QKline {
+    const MAX_LIMIT: i64 = 1000;
+
+    fn page(symbol: &str, interval: Interval, start: i64, end: i64, limit: i64) -> Self {
+        Self {
+            symbol: symbol.to_string(),
+            interval,
+            start_time: Some(start),
+            end_time: Some(end),
+            time_zone: None,
+            limit: Some(limit),
+        }
+    }
+}
+
+impl KlineQuery for rest::QKline {
+    const MAX_LIMIT: i64 = 1500;
+
+    fn page(symbol: &str, interval: Interval, start: i64, end: i64, limit: i64) -> Self {
+        Self {
+            symbol: symbol.to_string(),
+            interval,
+            start_time: Some(start),
+            end_time: Some(end),
+            limit: Some(limit),
+        }
+    }
+}
+
+/// 缺失的K线, 开盘时间在 `[start, end)` 内
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KlineGap {
+    pub start: i64,
+    pub end: i64,
+    /// 缺失的数量
+    pub missing: i64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct KlineHistory {
+    /// 按开盘时间排序, 没有重复
+    pub klines: Vec<Kline>,
+    pub gaps: Vec<KlineGap>,
+}
+
+/// 按 `startTime` 翻页下载一段时间内的K线
+///
+/// 通过 [`Executor`] 发送请求, 限频由客户端的 [`RateLimiter`](crate::api::RateLimiter) 控制,
+/// 收到限频错误时等待到 `retryAfter` 后重试
+pub struct KlineDownloader<E> {
+    executor: E,
+    page_limit: Option<i64>,
+    max_retries: usize,
+}
+
+impl<E: Executor> KlineDownloader<E> {
+    pub fn new(executor: E) -> Self {
+        Self {
+            executor,
+            page_limit: None,
+            max_retries: 3,
+        }
+    }
+
+    /// 每页数量, 默认为接口允许的最大值
+    pub fn with_page_limit(mut self, limit: i64) -> Self {
+        self.page_limit = Some(limit);
+        self
+    }
+
+    /// 限频和连接错误等可重试错误合计的最大重试次数
+    pub fn with_max_retries(mut self, max_retries: usize) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    pub fn into_inner(self) -> E {
+        self.executor
+    }
+
+    /// 下载开盘时间在 `[start, end]` 内的K线(ms)
+    pub fn download<Q: KlineQuery>(
+        &mut self,
+        symbol: &str,
+        interval: Interval,
+        start: i64,
+        end: i64,
+    ) -> Result<KlineHistory, ClientError> {
+        let limit = self
+            .page_limit
+            .unwrap_or(Q::MAX_LIMIT)
+            .clamp(1, Q::MAX_LIMIT);
+        let mut klines: Vec<Kline> = vec![];
+        let mut cursor = start;
+        while cursor <= end {
+            let page = self.fetch(Q::page(symbol, interval, cursor, end, limit))?;
+            let page_len = page.len() as i64;
+            let last_open = klines.last().map(|k| k.open_time).unwrap_or(i64::MIN);
+            klines.extend(
+                page.into_iter().filter(|k| {
+                    k.open_time > last_open && k.open_time >= start && k.open_time <= end
+                }),
+            );
+            debug!("{symbol} {interval} got {page_len} klines from {cursor}");
+            match klines.last() {
+                Some(last) if last.open_time >= cursor && page_len >= limit => {
+                    cursor = last.open_time + 1;
+                }
+                _ => break,
+            }
+        }
+        let gaps = find_gaps(&klines, interval, start, end.min(millis_ts()));
+        if !gaps.is_empty() {
+            warn!(
+                "{symbol} {interval} missing {} klines",
+                gaps.iter().map(|g| g.missing).sum::<i64>()
+            );
+        }
+        Ok(KlineHistory { klines, gaps })
+    }
+
+    fn fetch<Q: KlineQuery>(&mut self, query: Q) -> Result<Vec<Kline>, ClientError> {
+        execute_with_retry(&mut self.executor, query, self.max_retries).map(|resp| resp.result)
+    }
+}
+
+/// 检查相邻K线之间及区间首尾缺失的K线
+fn find_gaps(klines: &[Kline], interval: Interval, start: i64, end: i64) -> Vec<KlineGap> {
+    let mut gaps = vec![];
+    let mut push = |from: i64, to: i64| {
+        // 统计 `[from, to)` 内应有的K线数量
+        let mut opens = interval.open_times_between(from, to - 1);
+        let Some(first) = opens.next() else {
+            return;
+        };
+        let missing = match interval.fixed_millis() {
+            Some(step) => (to - 1 - first) / step + 1,
+            None => opens.count() as i64 + 1,
+        };
+        gaps.push(KlineGap {
+            start: first,
+            end: to,
+            missing,
+        });
+    };
+    let Some((first, last)) = klines.first().zip(klines.last()) else {
+        push(start, end + 1);
+        return gaps;
+    };
+    push(start, first.open_time);
+    for pair in klines.windows(2) {
+        push(interval.next_open(pair[0].open_time), pair[1].open_time);
+    }
+    push(interval.next_open(last.open_time), end + 1);
+    gaps
+}
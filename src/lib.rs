@@ -6,8 +6,27 @@ use time::OffsetDateTime;
 
 /// <https://binance-docs.github.io/apidocs/websocket_api/cn/#45fa4e00db>
 pub mod api;
+/// 统一 WebSocket API 和 REST 的请求执行
+pub mod executor;
+/// 由成交或小周期K线合成K线
+pub mod kline_aggregator;
+/// 历史K线下载
+pub mod kline_history;
+/// 用于离线测试的 WebSocket API 模拟服务器
+#[cfg(feature = "mock")]
+pub mod mock;
+/// 本地订单簿
+pub mod order_book;
 pub mod realtime_market;
 pub mod rest;
+/// 下单前风控
+pub mod risk;
+/// 交易对规则, 价格数量取整和下单前检查
+pub mod symbol_registry;
+/// 历史成交和订单下载
+pub mod trade_history;
+/// 自动维护 listenKey 的用户数据流
+pub mod user_data_stream;
 
 fn millis_ts() -> i64 {
     (OffsetDateTime::now_utc().unix_timestamp_nanos() / 1_000_000) as i64
@@ -71,7 +90,10 @@ pub enum FutureOrderType {
     ///限价止盈单
     TakeProfitMarket,
     ///限价只挂单
+    #[serde(rename = "TRAILING_STOP_MARKET")]
     TailingStopMarket,
+    /// 强平单, 仅出现在推送中
+    Liquidation,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -106,6 +128,7 @@ pub enum TimeInForce {
     IOC,
     FOK,
     GTX,
+    GTD,
 }
 
 /// 订单状态
@@ -131,6 +154,16 @@ pub enum OrderStatus {
     ExpiredInMatch,
 }
 
+impl OrderStatus {
+    /// 订单已结束, 不会再成交
+    pub fn is_final(&self) -> bool {
+        !matches!(
+            self,
+            Self::New | Self::PartiallyFilled | Self::PendingCancel
+        )
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum SymbolStatus {
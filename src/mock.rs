@@ -0,0 +1,323 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    net::{Shutdown, SocketAddr, TcpListener, TcpStream},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, AtomicI64, AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+
+use serde::Deserialize;
+use serde_json::{Value, json};
+use ws_tool::{
+    ServerBuilder,
+    codec::{StringCodec, default_handshake_handler},
+    errors::WsError,
+    frame::OpCode,
+};
+
+use crate::millis_ts;
+
+/// 收到的请求
+#[derive(Debug, Clone, Deserialize)]
+pub struct MockRequest {
+    pub id: u64,
+    pub method: String,
+    #[serde(default)]
+    pub params: Value,
+}
+
+/// 请求的响应
+#[derive(Debug, Clone)]
+pub enum MockReply {
+    /// status 200, 内容为 `result`
+    Ok(Value),
+    /// 错误响应
+    Err { status: i64, code: i64, msg: String },
+}
+
+impl MockReply {
+    pub fn err(status: i64, code: i64, msg: &str) -> Self {
+        MockReply::Err {
+            status,
+            code,
+            msg: msg.to_string(),
+        }
+    }
+}
+
+/// 注入的故障, 每个请求按顺序消耗一个
+#[derive(Debug, Clone)]
+pub enum Fault {
+    /// 忽略处理函数, 返回错误响应
+    Error { status: i64, code: i64, msg: String },
+    /// 响应前先发送 ping
+    Ping,
+    /// 延迟响应
+    Delay(Duration),
+    /// 不响应
+    NoResponse,
+    /// 缓存当前及之后共 n 个请求的响应, 然后逆序发送
+    Reorder(usize),
+    /// 不响应并断开连接
+    Disconnect,
+    /// 响应前先推送事件, 格式同 `userDataStream.subscribe` 的推送
+    Event(Value),
+    /// 响应前先发送任意文本帧
+    Frame(Value),
+}
+
+type Handler = Box<dyn Fn(&MockRequest) -> MockReply + Send + Sync>;
+
+#[derive(Default)]
+struct State {
+    handlers: Mutex<HashMap<String, Handler>>,
+    faults: Mutex<VecDeque<Fault>>,
+    requests: Mutex<Vec<MockRequest>>,
+    conns: Mutex<Vec<TcpStream>>,
+    conn_count: AtomicUsize,
+    order_id: AtomicI64,
+    closed: AtomicBool,
+}
+
+/// 本地 WebSocket API 模拟服务器
+///
+/// 默认处理 `ping`, `time`, `session.logon`, `order.place` 和用户数据订阅,
+/// 可以通过 [`MockServer::on`] 替换或增加处理函数, 通过 [`MockServer::inject`] 注入故障.
+/// drop 时关闭所有连接
+pub struct MockServer {
+    addr: SocketAddr,
+    state: Arc<State>,
+}
+
+impl MockServer {
+    /// 监听 127.0.0.1 上的随机端口
+    pub fn start() -> std::io::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        listener.set_nonblocking(true)?;
+        let addr = listener.local_addr()?;
+        let state = Arc::new(State::default());
+        let state_c = state.clone();
+        std::thread::Builder::new()
+            .name("mock-server".into())
+            .spawn(move || accept_loop(listener, state_c))?;
+        Ok(Self { addr, state })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub fn url(&self) -> String {
+        format!("ws://{}/ws-api/v3", self.addr)
+    }
+
+    /// 设置 `method` 的处理函数
+    pub fn on(
+        &self,
+        method: &str,
+        handler: impl Fn(&MockRequest) -> MockReply + Send + Sync + 'static,
+    ) {
+        self.state
+            .handlers
+            .lock()
+            .unwrap()
+            .insert(method.to_string(), Box::new(handler));
+    }
+
+    /// 注入的故障作用于之后的请求, 多次调用按顺序排队
+    pub fn inject(&self, fault: Fault) {
+        self.state.faults.lock().unwrap().push_back(fault);
+    }
+
+    /// 已收到的请求
+    pub fn requests(&self) -> Vec<MockRequest> {
+        self.state.requests.lock().unwrap().clone()
+    }
+
+    /// 累计建立的连接数
+    pub fn connection_count(&self) -> usize {
+        self.state.conn_count.load(Ordering::Relaxed)
+    }
+
+    /// 停止接受新的连接, 已有的连接不受影响
+    pub fn stop_accepting(&self) {
+        self.state.closed.store(true, Ordering::Relaxed);
+    }
+
+    /// 断开当前所有连接
+    pub fn disconnect_all(&self) {
+        for conn in self.state.conns.lock().unwrap().drain(..) {
+            conn.shutdown(Shutdown::Both).ok();
+        }
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.stop_accepting();
+        self.disconnect_all();
+    }
+}
+
+fn accept_loop(listener: TcpListener, state: Arc<State>) {
+    while !state.closed.load(Ordering::Relaxed) {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                let state = state.clone();
+                std::thread::spawn(move || {
+                    if let Err(e) = serve(stream, state) {
+                        tracing::debug!("mock connection exit: {e}");
+                    }
+                });
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                std::thread::sleep(Duration::from_millis(10));
+            }
+            Err(e) => {
+                tracing::warn!("mock server accept failed: {e}");
+                break;
+            }
+        }
+    }
+}
+
+fn serve(stream: TcpStream, state: Arc<State>) -> Result<(), WsError> {
+    stream.set_nonblocking(false)?;
+    let sock = stream.try_clone()?;
+    state.conns.lock().unwrap().push(stream.try_clone()?);
+    state.conn_count.fetch_add(1, Ordering::Relaxed);
+    let connected_since = millis_ts();
+    let mut codec = ServerBuilder::accept(stream, default_handshake_handler, StringCodec::factory)?;
+    let mut weight = 0;
+    let mut held: Vec<String> = vec![];
+    let mut hold_remaining = 0;
+    loop {
+        let msg = codec.receive()?;
+        match msg.code {
+            OpCode::Text => {}
+            OpCode::Close => return Ok(()),
+            _ => continue,
+        }
+        let req: MockRequest = match serde_json::from_str(&msg.data) {
+            Ok(req) => req,
+            Err(e) => {
+                let resp = error_frame(0, 400, -1000, &e.to_string(), weight);
+                codec.send(resp.as_str())?;
+                continue;
+            }
+        };
+        state.requests.lock().unwrap().push(req.clone());
+        weight += 1;
+        let fault = state.faults.lock().unwrap().pop_front();
+        let reply = match fault {
+            Some(Fault::Error { status, code, msg }) => MockReply::Err { status, code, msg },
+            Some(Fault::Ping) => {
+                codec.ping("mock")?;
+                handle(&state, &req, connected_since)
+            }
+            Some(Fault::Delay(delay)) => {
+                std::thread::sleep(delay);
+                handle(&state, &req, connected_since)
+            }
+            Some(Fault::NoResponse) => continue,
+            Some(Fault::Reorder(n)) => {
+                hold_remaining = n;
+                handle(&state, &req, connected_since)
+            }
+            Some(Fault::Event(event)) => {
+                let frame = json!({ "subscriptionId": 0, "event": event });
+                codec.send(frame.to_string().as_str())?;
+                handle(&state, &req, connected_since)
+            }
+            Some(Fault::Frame(frame)) => {
+                codec.send(frame.to_string().as_str())?;
+                handle(&state, &req, connected_since)
+            }
+            Some(Fault::Disconnect) => {
+                sock.shutdown(Shutdown::Both).ok();
+                return Ok(());
+            }
+            None => handle(&state, &req, connected_since),
+        };
+        let resp = match reply {
+            MockReply::Ok(result) => json!({
+                "id": req.id,
+                "status": 200,
+                "result": result,
+                "rateLimits": rate_limits(weight),
+            })
+            .to_string(),
+            MockReply::Err { status, code, msg } => error_frame(req.id, status, code, &msg, weight),
+        };
+        if hold_remaining > 0 {
+            held.push(resp);
+            hold_remaining -= 1;
+            if hold_remaining == 0 {
+                for resp in held.drain(..).rev() {
+                    codec.send(resp.as_str())?;
+                }
+            }
+        } else {
+            codec.send(resp.as_str())?;
+        }
+    }
+}
+
+fn handle(state: &State, req: &MockRequest, connected_since: i64) -> MockReply {
+    if let Some(handler) = state.handlers.lock().unwrap().get(&req.method) {
+        return handler(req);
+    }
+    let now = millis_ts();
+    match req.method.as_str() {
+        "ping" => MockReply::Ok(json!({})),
+        "time" => MockReply::Ok(json!({ "serverTime": now })),
+        "session.logon" => MockReply::Ok(json!({
+            "apiKey": req.params["apiKey"],
+            "authorizedSince": now,
+            "connectedSince": connected_since,
+            "returnRateLimits": true,
+            "serverTime": now,
+        })),
+        "order.place" => {
+            let order_id = state.order_id.fetch_add(1, Ordering::Relaxed) + 1;
+            let client_order_id = req.params["newClientOrderId"]
+                .as_str()
+                .map(|id| id.to_string())
+                .unwrap_or_else(|| format!("mock-{order_id}"));
+            MockReply::Ok(json!({
+                "symbol": req.params["symbol"],
+                "orderId": order_id,
+                "orderListId": -1,
+                "clientOrderId": client_order_id,
+                "transactTime": now,
+            }))
+        }
+        "userDataStream.subscribe" | "userDataStream.subscribe.signature" => {
+            MockReply::Ok(json!({ "subscriptionId": 0 }))
+        }
+        "userDataStream.unsubscribe" => MockReply::Ok(json!({})),
+        _ => MockReply::err(400, -1020, "This operation is not supported."),
+    }
+}
+
+fn error_frame(id: u64, status: i64, code: i64, msg: &str, weight: i64) -> String {
+    json!({
+        "id": id,
+        "status": status,
+        "error": { "code": code, "msg": msg },
+        "rateLimits": rate_limits(weight),
+    })
+    .to_string()
+}
+
+fn rate_limits(weight: i64) -> Value {
+    json!([{
+        "rateLimitType": "REQUEST_WEIGHT",
+        "interval": "MINUTE",
+        "intervalNum": 1,
+        "limit": 6000,
+        "count": weight,
+    }])
+}
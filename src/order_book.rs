@@ -0,0 +1,362 @@
+use std::collections::BTreeMap;
+
+use rust_decimal::Decimal;
+
+use crate::{
+    api::market::{Depth, PriceVol, QDepth},
+    realtime_market::DepthUpdateEvent,
+};
+
+/// 增量深度的连续性规则
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DepthRule {
+    /// 现货: 每个事件的 `U` 等于上一个事件的 `u + 1`
+    Spot,
+    /// 合约: 每个事件的 `pu` 等于上一个事件的 `u`
+    Future,
+}
+
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum OrderBookError {
+    /// 增量深度不连续, 订单簿已重置, 需要重新获取快照
+    #[error("depth update gap, expect {expected} got {got}")]
+    Gap { expected: i64, got: i64 },
+    /// 快照早于缓存的第一个事件, 需要重新获取快照
+    #[error("snapshot {snapshot} is older than first buffered update {first_update}")]
+    StaleSnapshot { snapshot: i64, first_update: i64 },
+}
+
+/// 根据快照和增量深度维护的本地订单簿
+///
+/// 按币安文档的同步流程: 先缓存 `<symbol>@depth` 推送, 获取 [`Depth`] 快照后丢弃过期的事件,
+/// 再按顺序应用其余事件. 发现 update id 不连续时返回 [`OrderBookError::Gap`],
+/// 这时订单簿已重置, 需要重新获取快照
+#[derive(Debug, Clone)]
+pub struct OrderBook {
+    symbol: String,
+    rule: DepthRule,
+    bids: BTreeMap<Decimal, Decimal>,
+    asks: BTreeMap<Decimal, Decimal>,
+    last_update_id: i64,
+    synced: bool,
+    /// 快照后还没有应用过事件
+    first_after_snapshot: bool,
+    buffer: Vec<DepthUpdateEvent>,
+}
+
+impl OrderBook {
+    pub fn new(symbol: &str, rule: DepthRule) -> Self {
+        Self {
+            symbol: symbol.to_uppercase(),
+            rule,
+            bids: Default::default(),
+            asks: Default::default(),
+            last_update_id: 0,
+            synced: false,
+            first_after_snapshot: false,
+            buffer: vec![],
+        }
+    }
+
+    pub fn symbol(&self) -> &str {
+        &self.symbol
+    }
+
+    /// 获取快照的请求
+    pub fn snapshot_query(&self, limit: Option<u64>) -> QDepth {
+        QDepth {
+            symbol: self.symbol.clone(),
+            limit,
+        }
+    }
+
+    pub fn is_synced(&self) -> bool {
+        self.synced
+    }
+
+    /// 已缓存推送但还没有应用快照
+    pub fn needs_snapshot(&self) -> bool {
+        !self.synced && !self.buffer.is_empty()
+    }
+
+    pub fn last_update_id(&self) -> i64 {
+        self.last_update_id
+    }
+
+    /// 清空订单簿和缓存, 等待新的快照
+    pub fn reset(&mut self) {
+        self.bids.clear();
+        self.asks.clear();
+        self.last_update_id = 0;
+        self.synced = false;
+        self.first_after_snapshot = false;
+        self.buffer.clear();
+    }
+
+    /// 处理一条增量深度推送, 未同步时先缓存
+    pub fn update(&mut self, event: DepthUpdateEvent) -> Result<(), OrderBookError> {
+        if !self.synced {
+            self.buffer.push(event);
+            return Ok(());
+        }
+        if let Err(e) = self.apply_event(&event) {
+            tracing::warn!("{} {e}, resync order book", self.symbol);
+            self.reset();
+            self.buffer.push(event);
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// 应用快照及缓存的推送
+    pub fn apply_snapshot(&mut self, depth: Depth) -> Result<(), OrderBookError> {
+        let snapshot = depth.last_update_id as i64;
+        let buffer = std::mem::take(&mut self.buffer);
+        if let Some(first) = buffer.first() {
+            let first_update = first.first_update_id;
+            let too_old = match self.rule {
+                DepthRule::Spot => snapshot + 1 < first_update,
+                DepthRule::Future => snapshot < first_update,
+            };
+            if too_old {
+                self.buffer = buffer;
+                return Err(OrderBookError::StaleSnapshot {
+                    snapshot,
+                    first_update,
+                });
+            }
+        }
+        self.bids = depth.bids.iter().map(|pv| (pv.0, pv.1)).collect();
+        self.asks = depth.asks.iter().map(|pv| (pv.0, pv.1)).collect();
+        self.last_update_id = snapshot;
+        self.synced = true;
+        self.first_after_snapshot = true;
+        let mut events = buffer.into_iter();
+        while let Some(event) = events.next() {
+            if let Err(e) = self.update(event) {
+                // 出错的事件已放回缓存, 之后的事件留给下一个快照
+                self.buffer.extend(events);
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    fn apply_event(&mut self, event: &DepthUpdateEvent) -> Result<(), OrderBookError> {
+        let last = self.last_update_id;
+        let future_first = self.rule == DepthRule::Future && self.first_after_snapshot;
+        if event.final_update_id < last || (event.final_update_id == last && !future_first) {
+            return Ok(());
+        }
+        let (expected, got, ok) = match (self.rule, self.first_after_snapshot) {
+            (DepthRule::Spot, true) => (
+                last + 1,
+                event.first_update_id,
+                event.first_update_id <= last + 1,
+            ),
+            (DepthRule::Spot, false) => (
+                last + 1,
+                event.first_update_id,
+                event.first_update_id == last + 1,
+            ),
+            (DepthRule::Future, true) => {
+                (last, event.first_update_id, event.first_update_id <= last)
+            }
+            (DepthRule::Future, false) => {
+                let pu = event.prev_final_update_id.unwrap_or_default();
+                (last, pu, pu == last)
+            }
+        };
+        if !ok {
+            return Err(OrderBookError::Gap { expected, got });
+        }
+        apply_levels(&mut self.bids, &event.bids);
+        apply_levels(&mut self.asks, &event.asks);
+        self.last_update_id = event.final_update_id;
+        self.first_after_snapshot = false;
+        Ok(())
+    }
+
+    pub fn best_bid(&self) -> Option<PriceVol> {
+        self.bids.iter().next_back().map(|(p, v)| PriceVol(*p, *v))
+    }
+
+    pub fn best_ask(&self) -> Option<PriceVol> {
+        self.asks.iter().next().map(|(p, v)| PriceVol(*p, *v))
+    }
+
+    /// 价格从高到低的前 n 档买单
+    pub fn bids(&self, n: usize) -> Vec<PriceVol> {
+        self.bids
+            .iter()
+            .rev()
+            .take(n)
+            .map(|(p, v)| PriceVol(*p, *v))
+            .collect()
+    }
+
+    /// 价格从低到高的前 n 档卖单
+    pub fn asks(&self, n: usize) -> Vec<PriceVol> {
+        self.asks
+            .iter()
+            .take(n)
+            .map(|(p, v)| PriceVol(*p, *v))
+            .collect()
+    }
+
+    /// 价格不低于 `price` 的买单总量
+    pub fn bid_depth_to(&self, price: Decimal) -> Decimal {
+        self.bids.range(price..).map(|(_, v)| *v).sum()
+    }
+
+    /// 价格不高于 `price` 的卖单总量
+    pub fn ask_depth_to(&self, price: Decimal) -> Decimal {
+        self.asks.range(..=price).map(|(_, v)| *v).sum()
+    }
+}
+
+fn apply_levels(book: &mut BTreeMap<Decimal, Decimal>, levels: &[PriceVol]) {
+    for PriceVol(price, qty) in levels {
+        if qty.is_zero() {
+            book.remove(price);
+        } else {
+            book.insert(*price, *qty);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn levels(levels: &[(i64, i64)]) -> Vec<PriceVol> {
+        levels
+            .iter()
+            .map(|(p, q)| PriceVol(Decimal::from(*p), Decimal::from(*q)))
+            .collect()
+    }
+
+    fn depth(last_update_id: usize, bids: &[(i64, i64)], asks: &[(i64, i64)]) -> Depth {
+        Depth {
+            last_update_id,
+            bids: levels(bids),
+            asks: levels(asks),
+        }
+    }
+
+    fn event(first: i64, last: i64, prev: Option<i64>, bids: &[(i64, i64)]) -> DepthUpdateEvent {
+        DepthUpdateEvent {
+            event_type: "depthUpdate".into(),
+            event_time: 0,
+            trade_time: None,
+            symbol: "BTCUSDT".into(),
+            first_update_id: first,
+            final_update_id: last,
+            prev_final_update_id: prev,
+            bids: levels(bids),
+            asks: vec![],
+        }
+    }
+
+    fn best_bid(book: &OrderBook) -> (Decimal, Decimal) {
+        let PriceVol(price, qty) = book.best_bid().unwrap();
+        (price, qty)
+    }
+
+    #[test]
+    fn spot_snapshot_drops_old_events_and_bridges() {
+        let mut book = OrderBook::new("btcusdt", DepthRule::Spot);
+        book.update(event(90, 99, None, &[(10, 9)])).unwrap();
+        book.update(event(100, 105, None, &[(10, 1)])).unwrap();
+        book.update(event(106, 110, None, &[(11, 2)])).unwrap();
+        assert!(book.needs_snapshot());
+        book.apply_snapshot(depth(102, &[(10, 5)], &[(12, 1)]))
+            .unwrap();
+        assert!(book.is_synced());
+        assert_eq!(book.last_update_id(), 110);
+        assert_eq!(best_bid(&book), (11.into(), 2.into()));
+        assert_eq!(book.bids(2)[1].1, Decimal::ONE);
+        // 数量为 0 删除价位
+        book.update(event(111, 111, None, &[(11, 0)])).unwrap();
+        assert_eq!(best_bid(&book), (10.into(), 1.into()));
+    }
+
+    #[test]
+    fn stale_snapshot_keeps_buffer() {
+        let mut book = OrderBook::new("BTCUSDT", DepthRule::Spot);
+        book.update(event(100, 105, None, &[])).unwrap();
+        assert!(matches!(
+            book.apply_snapshot(depth(98, &[], &[])),
+            Err(OrderBookError::StaleSnapshot {
+                snapshot: 98,
+                first_update: 100
+            })
+        ));
+        assert!(!book.is_synced());
+        book.apply_snapshot(depth(99, &[], &[])).unwrap();
+        assert_eq!(book.last_update_id(), 105);
+    }
+
+    #[test]
+    fn spot_gap_resets_and_buffers() {
+        let mut book = OrderBook::new("BTCUSDT", DepthRule::Spot);
+        book.apply_snapshot(depth(100, &[(10, 1)], &[])).unwrap();
+        book.update(event(101, 102, None, &[])).unwrap();
+        assert!(matches!(
+            book.update(event(104, 105, None, &[])),
+            Err(OrderBookError::Gap {
+                expected: 103,
+                got: 104
+            })
+        ));
+        assert!(!book.is_synced());
+        assert!(book.best_bid().is_none());
+        assert!(book.needs_snapshot());
+    }
+
+    #[test]
+    fn gap_in_buffer_keeps_later_events() {
+        let mut book = OrderBook::new("BTCUSDT", DepthRule::Spot);
+        book.update(event(101, 102, None, &[])).unwrap();
+        book.update(event(105, 106, None, &[])).unwrap();
+        book.update(event(107, 108, None, &[(10, 3)])).unwrap();
+        assert!(matches!(
+            book.apply_snapshot(depth(100, &[], &[])),
+            Err(OrderBookError::Gap { .. })
+        ));
+        // 出错的事件和之后的事件都保留, 用新的快照继续同步
+        book.apply_snapshot(depth(104, &[(9, 1)], &[])).unwrap();
+        assert_eq!(book.last_update_id(), 108);
+        assert_eq!(best_bid(&book), (10.into(), 3.into()));
+    }
+
+    #[test]
+    fn future_first_event_bridges_then_checks_pu() {
+        let mut book = OrderBook::new("BTCUSDT", DepthRule::Future);
+        book.update(event(95, 99, Some(94), &[])).unwrap();
+        book.update(event(100, 110, Some(99), &[(10, 1)])).unwrap();
+        book.update(event(111, 115, Some(110), &[(11, 1)])).unwrap();
+        book.apply_snapshot(depth(105, &[], &[])).unwrap();
+        assert_eq!(book.last_update_id(), 115);
+        assert_eq!(best_bid(&book), (11.into(), 1.into()));
+        assert!(matches!(
+            book.update(event(117, 120, Some(116), &[])),
+            Err(OrderBookError::Gap {
+                expected: 115,
+                got: 116
+            })
+        ));
+    }
+
+    #[test]
+    fn future_snapshot_older_than_first_event() {
+        let mut book = OrderBook::new("BTCUSDT", DepthRule::Future);
+        book.update(event(100, 110, Some(99), &[])).unwrap();
+        assert!(matches!(
+            book.apply_snapshot(depth(99, &[], &[])),
+            Err(OrderBookError::StaleSnapshot { .. })
+        ));
+        book.apply_snapshot(depth(100, &[], &[])).unwrap();
+        assert_eq!(book.last_update_id(), 110);
+    }
+}
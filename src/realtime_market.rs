@@ -13,7 +13,13 @@ use ws_tool::{
     stream::{SyncStream, SyncStreamRead, SyncStreamWrite},
 };
 
-use crate::{OrderSide, OrderStatus, OrderType, TimeInForce, api::market::Kline};
+use crate::{
+    FutureOrderType, OrderSide, OrderStatus, OrderType, TimeInForce,
+    api::{
+        market::{AggTrade, Depth, Kline, PriceVol},
+        trade::PositionSide,
+    },
+};
 
 pub const SPOT_MARKET_URL: &str = "wss://stream.binance.com:9443/stream";
 pub const FUTURE_MARKET_URL: &str = "wss://fstream.binance.com/stream";
@@ -176,6 +182,15 @@ impl AutoReconnectMarketClient {
             }
         }
     }
+
+    /// 接收下一条行情推送并按 stream 类型解析, 跳过订阅响应
+    pub fn recv_event(&mut self) -> Result<MarketEvent, WsError> {
+        loop {
+            if let Some(event) = parse_event(&self.recv()?) {
+                break Ok(event);
+            }
+        }
+    }
 }
 
 pub struct MarketClient {
@@ -249,6 +264,15 @@ impl MarketClient {
             }
         }
     }
+
+    /// 接收下一条行情推送并按 stream 类型解析, 跳过订阅响应
+    pub fn recv_event(&mut self) -> Result<MarketEvent, WsError> {
+        loop {
+            if let Some(event) = parse_event(&self.recv()?) {
+                break Ok(event);
+            }
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default, Hash)]
@@ -339,6 +363,129 @@ impl std::fmt::Display for Interval {
     }
 }
 
+const MINUTE_MS: i64 = 60_000;
+const DAY_MS: i64 = 1440 * MINUTE_MS;
+/// 1970-01-01 是周四, 周线从周一 00:00 (UTC) 开始
+const WEEK_OFFSET_MS: i64 = 4 * DAY_MS;
+
+impl Interval {
+    /// 固定周期的毫秒数, 月线长度不固定, 返回 `None`
+    pub fn fixed_millis(&self) -> Option<i64> {
+        Some(match self {
+            Self::Sec1 => 1_000,
+            Self::Min1 => MINUTE_MS,
+            Self::Min3 => 3 * MINUTE_MS,
+            Self::Min5 => 5 * MINUTE_MS,
+            Self::Min15 => 15 * MINUTE_MS,
+            Self::Min30 => 30 * MINUTE_MS,
+            Self::Hour1 => 60 * MINUTE_MS,
+            Self::Hour2 => 120 * MINUTE_MS,
+            Self::Hour4 => 240 * MINUTE_MS,
+            Self::Hour6 => 360 * MINUTE_MS,
+            Self::Hour8 => 480 * MINUTE_MS,
+            Self::Hour12 => 720 * MINUTE_MS,
+            Self::Day1 => DAY_MS,
+            Self::Day3 => 3 * DAY_MS,
+            Self::Week1 => 7 * DAY_MS,
+            Self::Month1 => return None,
+        })
+    }
+
+    /// `ts` 所在K线的长度(ms), 月线按实际天数计算
+    pub fn millis_at(&self, ts: i64) -> i64 {
+        self.next_open(ts) - self.align(ts)
+    }
+
+    /// `ts` 所在K线的开盘时间(ms, UTC)
+    ///
+    /// 日内周期和日线, 3日线按 1970-01-01 起的整数倍对齐, 周线从周一开始, 月线从每月 1 日开始
+    pub fn align(&self, ts: i64) -> i64 {
+        match self {
+            Self::Week1 => ts - (ts - WEEK_OFFSET_MS).rem_euclid(7 * DAY_MS),
+            Self::Month1 => month_open(ts, 0).unwrap_or(ts),
+            _ => {
+                let step = self.fixed_millis().unwrap_or(MINUTE_MS);
+                ts - ts.rem_euclid(step)
+            }
+        }
+    }
+
+    /// `ts` 所在K线的下一根K线的开盘时间(ms)
+    pub fn next_open(&self, ts: i64) -> i64 {
+        match self.fixed_millis() {
+            Some(step) => self.align(ts) + step,
+            None => month_open(ts, 1).unwrap_or(i64::MAX),
+        }
+    }
+
+    /// 开盘时间在 `[start, end]` 内的所有K线开盘时间
+    pub fn open_times_between(&self, start: i64, end: i64) -> impl Iterator<Item = i64> {
+        let interval = *self;
+        let first = match interval.align(start) {
+            open if open == start => start,
+            _ => interval.next_open(start),
+        };
+        std::iter::successors(Some(first), move |&open| Some(interval.next_open(open)))
+            .take_while(move |&open| open <= end)
+    }
+}
+
+/// `ts` 所在月份之后第 `months` 个月 1 日 00:00 (UTC) 的时间戳(ms), 超出范围时返回 `None`
+fn month_open(ts: i64, months: u8) -> Option<i64> {
+    let epoch = time::OffsetDateTime::UNIX_EPOCH.date();
+    let date = epoch.checked_add(time::Duration::days(ts.div_euclid(DAY_MS)))?;
+    let (mut year, mut month) = (date.year(), date.month());
+    for _ in 0..months {
+        if month == time::Month::December {
+            year += 1;
+        }
+        month = month.next();
+    }
+    let open = time::Date::from_calendar_date(year, month, 1).ok()?;
+    Some((open - epoch).whole_days() * DAY_MS)
+}
+
+impl TryFrom<Interval> for time::Duration {
+    type Error = String;
+
+    /// 月线长度不固定, 无法转换
+    fn try_from(value: Interval) -> Result<Self, Self::Error> {
+        value
+            .fixed_millis()
+            .map(time::Duration::milliseconds)
+            .ok_or_else(|| format!("interval {value} has no fixed duration"))
+    }
+}
+
+impl TryFrom<time::Duration> for Interval {
+    type Error = String;
+
+    fn try_from(value: time::Duration) -> Result<Self, Self::Error> {
+        const FIXED: [Interval; 15] = [
+            Interval::Sec1,
+            Interval::Min1,
+            Interval::Min3,
+            Interval::Min5,
+            Interval::Min15,
+            Interval::Min30,
+            Interval::Hour1,
+            Interval::Hour2,
+            Interval::Hour4,
+            Interval::Hour6,
+            Interval::Hour8,
+            Interval::Hour12,
+            Interval::Day1,
+            Interval::Day3,
+            Interval::Week1,
+        ];
+        let millis = value.whole_milliseconds();
+        FIXED
+            .into_iter()
+            .find(|i| i.fixed_millis().map(i128::from) == Some(millis))
+            .ok_or_else(|| format!("no interval matches duration {value}"))
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct EventData<T> {
@@ -544,6 +691,150 @@ pub struct StreamData {
     pub data: serde_json::Value,
 }
 
+/// 归集交易 `<symbol>@aggTrade`
+pub type AggTradeEvent = EventData<AggTrade>;
+
+impl AggTradeEvent {
+    pub fn params(symbols: Vec<String>) -> Vec<String> {
+        symbols
+            .into_iter()
+            .map(|s| format!("{}@aggTrade", s.to_lowercase()))
+            .collect()
+    }
+}
+
+/// 逐笔交易
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RawTrade {
+    /// 交易ID
+    #[serde(rename = "t")]
+    pub trade_id: i64,
+    #[serde(rename = "p")]
+    pub price: Decimal,
+    #[serde(rename = "q")]
+    pub qty: Decimal,
+    /// 成交时间(ms)
+    #[serde(rename = "T")]
+    pub time: i64,
+    /// 买方是否为挂单方
+    #[serde(rename = "m")]
+    pub is_buyer_maker: bool,
+    #[serde(rename = "M", default)]
+    pub is_best_match: bool,
+}
+
+/// 逐笔交易 `<symbol>@trade`
+pub type TradeEvent = EventData<RawTrade>;
+
+impl TradeEvent {
+    pub fn params(symbols: Vec<String>) -> Vec<String> {
+        symbols
+            .into_iter()
+            .map(|s| format!("{}@trade", s.to_lowercase()))
+            .collect()
+    }
+}
+
+/// 增量深度 `<symbol>@depth`, `<symbol>@depth@100ms`
+#[derive(Debug, Clone, Deserialize)]
+pub struct DepthUpdateEvent {
+    #[serde(rename = "e")]
+    pub event_type: String,
+    /// 事件时间(ms)
+    #[serde(rename = "E")]
+    pub event_time: i64,
+    /// 撮合时间(ms), 仅合约
+    #[serde(rename = "T", default)]
+    pub trade_time: Option<i64>,
+    #[serde(rename = "s")]
+    pub symbol: String,
+    /// 从上次推送至今新增的第一个 update id
+    #[serde(rename = "U")]
+    pub first_update_id: i64,
+    /// 从上次推送至今新增的最后一个 update id
+    #[serde(rename = "u")]
+    pub final_update_id: i64,
+    /// 上次推送的最后一个 update id, 仅合约
+    #[serde(rename = "pu", default)]
+    pub prev_final_update_id: Option<i64>,
+    /// 变动的买单, 数量为 0 表示删除该价位
+    #[serde(rename = "b")]
+    pub bids: Vec<PriceVol>,
+    /// 变动的卖单
+    #[serde(rename = "a")]
+    pub asks: Vec<PriceVol>,
+}
+
+/// 按 stream 名称解析后的行情推送
+#[derive(Debug, Clone)]
+pub enum MarketEvent {
+    /// `<symbol>@kline_<interval>`
+    Kline(KlineEvent),
+    /// `<symbol>@bookTicker`, `!bookTicker`
+    BookTicker(BookTickerEvent),
+    /// `<symbol>@miniTicker`
+    MiniTicker(MiniTicker),
+    /// `!miniTicker@arr`
+    MiniTickers(Vec<MiniTicker>),
+    /// `<symbol>@aggTrade`
+    AggTrade(AggTradeEvent),
+    /// `<symbol>@trade`
+    Trade(TradeEvent),
+    DepthUpdate(DepthUpdateEvent),
+    /// `<symbol>@depth<levels>`
+    PartialDepth {
+        symbol: String,
+        depth: Depth,
+    },
+    /// 未识别或解析失败的推送
+    Unknown(StreamData),
+}
+
+impl MarketEvent {
+    pub fn from_stream(data: StreamData) -> Self {
+        let stream = data.stream.as_str();
+        // 全市场的 stream 以 `!` 开头, 如 `!miniTicker@arr`
+        let (symbol, kind) = match stream.starts_with('!') {
+            true => ("", stream),
+            false => stream.split_once('@').unwrap_or(("", stream)),
+        };
+        let kind = kind.split('@').next().unwrap_or_default();
+        let event = match kind {
+            k if k.starts_with("kline_") => KlineEvent::deserialize(&data.data).map(Self::Kline),
+            "bookTicker" | "!bookTicker" => {
+                BookTickerEvent::deserialize(&data.data).map(Self::BookTicker)
+            }
+            "miniTicker" => MiniTicker::deserialize(&data.data).map(Self::MiniTicker),
+            "!miniTicker" => Vec::deserialize(&data.data).map(Self::MiniTickers),
+            "aggTrade" => AggTradeEvent::deserialize(&data.data).map(Self::AggTrade),
+            "trade" => TradeEvent::deserialize(&data.data).map(Self::Trade),
+            "depth" => DepthUpdateEvent::deserialize(&data.data).map(Self::DepthUpdate),
+            "depth5" | "depth10" | "depth20" => {
+                Depth::deserialize(&data.data).map(|depth| Self::PartialDepth {
+                    symbol: symbol.to_uppercase(),
+                    depth,
+                })
+            }
+            _ => return Self::Unknown(data),
+        };
+        event.unwrap_or_else(|e| {
+            tracing::warn!("failed to parse {} event: {e}", data.stream);
+            Self::Unknown(data)
+        })
+    }
+}
+
+/// 解析推送消息, 订阅请求的响应等非行情消息返回 `None`
+fn parse_event(msg: &str) -> Option<MarketEvent> {
+    match serde_json::from_str::<StreamData>(msg) {
+        Ok(data) => Some(MarketEvent::from_stream(data)),
+        Err(_) => {
+            debug!("skip non stream message {msg}");
+            None
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase", tag = "e")]
 pub enum UserStreamData {
@@ -654,3 +945,472 @@ pub struct ExecutionReport {
     #[serde(rename = "O")]
     pub create_time: i64,
 }
+
+/// 合约用户数据推送
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE", tag = "e")]
+pub enum FutureUserStreamData {
+    #[serde(rename = "listenKeyExpired")]
+    ListenKeyExpired(ListenKeyExpired),
+    OrderTradeUpdate(Box<OrderTradeUpdate>),
+    AccountUpdate(AccountUpdate),
+    MarginCall(MarginCall),
+    AccountConfigUpdate(AccountConfigUpdate),
+    TradeLite(TradeLite),
+}
+
+/// 订单/交易更新 ORDER_TRADE_UPDATE
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrderTradeUpdate {
+    /// 事件推送时间
+    #[serde(rename = "E")]
+    pub event_time: i64,
+    /// 撮合时间
+    #[serde(rename = "T")]
+    pub trade_time: i64,
+    #[serde(rename = "o")]
+    pub order: FutureOrderUpdate,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FutureOrderUpdate {
+    #[serde(rename = "s")]
+    pub symbol: String,
+    #[serde(rename = "c")]
+    pub client_order_id: String,
+    #[serde(rename = "S")]
+    pub side: OrderSide,
+    #[serde(rename = "o")]
+    pub order_type: FutureOrderType,
+    #[serde(rename = "f")]
+    pub time_in_force: TimeInForce,
+    /// 原始数量
+    #[serde(rename = "q")]
+    pub origin_qty: Decimal,
+    /// 原始价格
+    #[serde(rename = "p")]
+    pub origin_price: Decimal,
+    /// 成交均价
+    #[serde(rename = "ap")]
+    pub avg_price: Decimal,
+    /// 触发价
+    #[serde(rename = "sp")]
+    pub stop_price: Decimal,
+    /// 本次事件的具体执行类型 NEW, CANCELED, CALCULATED, EXPIRED, TRADE, AMENDMENT
+    #[serde(rename = "x")]
+    pub exec_type: String,
+    #[serde(rename = "X")]
+    pub order_status: OrderStatus,
+    #[serde(rename = "i")]
+    pub order_id: i64,
+    /// 末次成交量
+    #[serde(rename = "l")]
+    pub last_filled_qty: Decimal,
+    /// 累计成交量
+    #[serde(rename = "z")]
+    pub filled_qty: Decimal,
+    /// 末次成交价格
+    #[serde(rename = "L")]
+    pub last_filled_price: Decimal,
+    /// 手续费资产类型
+    #[serde(rename = "N", default)]
+    pub commission_asset: Option<String>,
+    /// 手续费数量
+    #[serde(rename = "n", default)]
+    pub commission: Decimal,
+    /// 成交时间
+    #[serde(rename = "T")]
+    pub trade_time: i64,
+    #[serde(rename = "t")]
+    pub trade_id: i64,
+    /// 买单净值
+    #[serde(rename = "b")]
+    pub bids_notional: Decimal,
+    /// 卖单净值
+    #[serde(rename = "a")]
+    pub ask_notional: Decimal,
+    #[serde(rename = "m")]
+    pub is_maker: bool,
+    #[serde(rename = "R")]
+    pub reduce_only: bool,
+    /// 触发价类型
+    #[serde(rename = "wt")]
+    pub working_type: String,
+    /// 原始订单类型
+    #[serde(rename = "ot")]
+    pub origin_order_type: FutureOrderType,
+    #[serde(rename = "ps")]
+    pub position_side: PositionSide,
+    /// 是否为触发平仓单
+    #[serde(rename = "cp")]
+    pub close_position: bool,
+    /// 追踪止损激活价格
+    #[serde(rename = "AP", default)]
+    pub activation_price: Option<Decimal>,
+    /// 追踪止损回调比例
+    #[serde(rename = "cr", default)]
+    pub callback_rate: Option<Decimal>,
+    /// 该交易实现盈亏
+    #[serde(rename = "rp")]
+    pub realized_profit: Decimal,
+    /// 自成交防止模式
+    #[serde(rename = "V", default)]
+    pub self_trade_prevention_mode: Option<String>,
+    #[serde(rename = "gtd", default)]
+    pub good_till_date: i64,
+}
+
+/// 余额和持仓更新 ACCOUNT_UPDATE
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountUpdate {
+    /// 事件推送时间
+    #[serde(rename = "E")]
+    pub event_time: i64,
+    /// 撮合时间
+    #[serde(rename = "T")]
+    pub trade_time: i64,
+    #[serde(rename = "a")]
+    pub data: AccountUpdateData,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountUpdateData {
+    /// 事件推出原因 DEPOSIT, WITHDRAW, ORDER, FUNDING_FEE 等
+    #[serde(rename = "m")]
+    pub reason: String,
+    #[serde(rename = "B")]
+    pub balances: Vec<FutureBalance>,
+    #[serde(rename = "P")]
+    pub positions: Vec<FuturePosition>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FutureBalance {
+    #[serde(rename = "a")]
+    pub asset: String,
+    /// 钱包余额
+    #[serde(rename = "wb")]
+    pub wallet_balance: Decimal,
+    /// 除去逐仓仓位保证金的钱包余额
+    #[serde(rename = "cw")]
+    pub cross_wallet_balance: Decimal,
+    /// 除去盈亏与交易手续费以外的钱包余额改变量
+    #[serde(rename = "bc")]
+    pub balance_change: Decimal,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FuturePosition {
+    #[serde(rename = "s")]
+    pub symbol: String,
+    /// 仓位
+    #[serde(rename = "pa")]
+    pub position_amt: Decimal,
+    /// 入仓价格
+    #[serde(rename = "ep")]
+    pub entry_price: Decimal,
+    /// 盈亏平衡价
+    #[serde(rename = "bep")]
+    pub break_even_price: Decimal,
+    /// (费前)累计实现损益
+    #[serde(rename = "cr")]
+    pub accumulated_realized: Decimal,
+    /// 持仓未实现盈亏
+    #[serde(rename = "up")]
+    pub unrealized_profit: Decimal,
+    /// 保证金模式 isolated, cross
+    #[serde(rename = "mt")]
+    pub margin_type: String,
+    /// 若为逐仓, 仓位保证金
+    #[serde(rename = "iw")]
+    pub isolated_wallet: Decimal,
+    #[serde(rename = "ps")]
+    pub position_side: PositionSide,
+}
+
+/// 追加保证金通知 MARGIN_CALL
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MarginCall {
+    /// 事件推送时间
+    #[serde(rename = "E")]
+    pub event_time: i64,
+    /// 除去逐仓仓位保证金的钱包余额, 仅在全仓 margin call 情况下推送此字段
+    #[serde(rename = "cw", default)]
+    pub cross_wallet_balance: Option<Decimal>,
+    #[serde(rename = "p")]
+    pub positions: Vec<MarginCallPosition>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MarginCallPosition {
+    #[serde(rename = "s")]
+    pub symbol: String,
+    #[serde(rename = "ps")]
+    pub position_side: PositionSide,
+    #[serde(rename = "pa")]
+    pub position_amt: Decimal,
+    /// 保证金模式 CROSSED, ISOLATED
+    #[serde(rename = "mt")]
+    pub margin_type: String,
+    /// 若为逐仓, 仓位保证金
+    #[serde(rename = "iw")]
+    pub isolated_wallet: Decimal,
+    /// 标记价格
+    #[serde(rename = "mp")]
+    pub mark_price: Decimal,
+    /// 未实现盈亏
+    #[serde(rename = "up")]
+    pub unrealized_profit: Decimal,
+    /// 持仓需要的维持保证金
+    #[serde(rename = "mm")]
+    pub maint_margin: Decimal,
+}
+
+/// 杠杆倍数等账户配置更新 ACCOUNT_CONFIG_UPDATE
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountConfigUpdate {
+    /// 事件推送时间
+    #[serde(rename = "E")]
+    pub event_time: i64,
+    /// 撮合时间
+    #[serde(rename = "T")]
+    pub trade_time: i64,
+    /// 交易对杠杆倍数变化
+    #[serde(rename = "ac", default)]
+    pub leverage: Option<LeverageUpdate>,
+    /// 联合保证金状态变化
+    #[serde(rename = "ai", default)]
+    pub multi_assets: Option<MultiAssetsUpdate>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LeverageUpdate {
+    #[serde(rename = "s")]
+    pub symbol: String,
+    #[serde(rename = "l")]
+    pub leverage: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MultiAssetsUpdate {
+    /// 联合保证金模式是否开启
+    #[serde(rename = "j")]
+    pub enabled: bool,
+}
+
+/// 精简交易推送 TRADE_LITE
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TradeLite {
+    /// 事件推送时间
+    #[serde(rename = "E")]
+    pub event_time: i64,
+    /// 撮合时间
+    #[serde(rename = "T")]
+    pub trade_time: i64,
+    #[serde(rename = "s")]
+    pub symbol: String,
+    /// 原始数量
+    #[serde(rename = "q")]
+    pub origin_qty: Decimal,
+    /// 原始价格
+    #[serde(rename = "p")]
+    pub origin_price: Decimal,
+    #[serde(rename = "m")]
+    pub is_maker: bool,
+    #[serde(rename = "c")]
+    pub client_order_id: String,
+    #[serde(rename = "S")]
+    pub side: OrderSide,
+    /// 末次成交价格
+    #[serde(rename = "L")]
+    pub last_filled_price: Decimal,
+    /// 末次成交量
+    #[serde(rename = "l")]
+    pub last_filled_qty: Decimal,
+    #[serde(rename = "t")]
+    pub trade_id: i64,
+    #[serde(rename = "i")]
+    pub order_id: i64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// UTC 时间的毫秒时间戳
+    fn ts(year: i32, month: u8, day: u8, hour: u8, minute: u8) -> i64 {
+        let month = time::Month::try_from(month).unwrap();
+        let date = time::Date::from_calendar_date(year, month, day).unwrap();
+        let dt = date.with_hms(hour, minute, 0).unwrap().assume_utc();
+        (dt.unix_timestamp_nanos() / 1_000_000) as i64
+    }
+
+    #[test]
+    fn fixed_interval_align_and_next_open() {
+        let t = ts(2024, 3, 5, 13, 47) + 12_345;
+        assert_eq!(Interval::Min1.align(t), ts(2024, 3, 5, 13, 47));
+        assert_eq!(Interval::Min15.align(t), ts(2024, 3, 5, 13, 45));
+        assert_eq!(Interval::Min15.next_open(t), ts(2024, 3, 5, 14, 0));
+        assert_eq!(Interval::Hour4.align(t), ts(2024, 3, 5, 12, 0));
+        assert_eq!(Interval::Day1.next_open(t), ts(2024, 3, 6, 0, 0));
+        // 开盘时间本身对齐到自己
+        let open = ts(2024, 3, 5, 12, 0);
+        assert_eq!(Interval::Hour4.align(open), open);
+        assert_eq!(Interval::Hour4.next_open(open), ts(2024, 3, 5, 16, 0));
+        assert_eq!(Interval::Sec1.millis_at(t), 1_000);
+    }
+
+    #[test]
+    fn week_starts_on_monday() {
+        // 2024-01-01 是周一
+        let monday = ts(2024, 1, 1, 0, 0);
+        assert_eq!(Interval::Week1.align(monday), monday);
+        assert_eq!(Interval::Week1.align(ts(2024, 1, 7, 23, 59)), monday);
+        assert_eq!(Interval::Week1.align(ts(2024, 1, 3, 8, 0)), monday);
+        assert_eq!(Interval::Week1.next_open(monday), ts(2024, 1, 8, 0, 0));
+        // 1970-01-01 是周四, 所在周从 1969-12-29 开始
+        assert_eq!(Interval::Week1.align(0), -3 * DAY_MS);
+    }
+
+    #[test]
+    fn month_ends_and_leap_years() {
+        let month = Interval::Month1;
+        assert_eq!(month.align(ts(2024, 1, 31, 23, 59)), ts(2024, 1, 1, 0, 0));
+        assert_eq!(
+            month.next_open(ts(2024, 1, 31, 23, 59)),
+            ts(2024, 2, 1, 0, 0)
+        );
+        assert_eq!(month.align(ts(2024, 2, 29, 12, 0)), ts(2024, 2, 1, 0, 0));
+        assert_eq!(month.millis_at(ts(2024, 2, 10, 0, 0)), 29 * DAY_MS);
+        assert_eq!(month.millis_at(ts(2023, 2, 10, 0, 0)), 28 * DAY_MS);
+        assert_eq!(month.millis_at(ts(2100, 2, 10, 0, 0)), 28 * DAY_MS);
+        assert_eq!(month.millis_at(ts(2000, 2, 10, 0, 0)), 29 * DAY_MS);
+        assert_eq!(month.millis_at(ts(2024, 4, 30, 0, 0)), 30 * DAY_MS);
+        // 12 月跨年
+        assert_eq!(
+            month.next_open(ts(2023, 12, 31, 23, 59)),
+            ts(2024, 1, 1, 0, 0)
+        );
+        assert_eq!(month.fixed_millis(), None);
+    }
+
+    #[test]
+    fn open_times_between_bounds() {
+        let start = ts(2024, 3, 5, 13, 0);
+        let times = Interval::Hour1
+            .open_times_between(start, ts(2024, 3, 5, 16, 0))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            times,
+            [13, 14, 15, 16].map(|h| ts(2024, 3, 5, h, 0)).to_vec()
+        );
+        // 未对齐的起点从下一根开始, 终点不足一根时不包含
+        let times = Interval::Hour1
+            .open_times_between(start + 1, ts(2024, 3, 5, 15, 59))
+            .collect::<Vec<_>>();
+        assert_eq!(times, [14, 15].map(|h| ts(2024, 3, 5, h, 0)).to_vec());
+        let months = Interval::Month1
+            .open_times_between(ts(2023, 11, 15, 0, 0), ts(2024, 3, 1, 0, 0))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            months,
+            vec![
+                ts(2023, 12, 1, 0, 0),
+                ts(2024, 1, 1, 0, 0),
+                ts(2024, 2, 1, 0, 0),
+                ts(2024, 3, 1, 0, 0),
+            ]
+        );
+        assert_eq!(Interval::Day1.open_times_between(10, 5).count(), 0);
+    }
+
+    fn parse(json: &str) -> FutureUserStreamData {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn order_trade_update_with_gtd() {
+        let data = parse(
+            r#"{"e":"ORDER_TRADE_UPDATE","E":1568879465651,"T":1568879465650,"o":{
+            "s":"BTCUSDT","c":"TEST","S":"SELL","o":"LIMIT","f":"GTD","q":"0.001",
+            "p":"90000","ap":"0","sp":"0","x":"NEW","X":"NEW","i":8886774,"l":"0",
+            "z":"0","L":"0","N":"USDT","n":"0","T":1568879465650,"t":0,"b":"0",
+            "a":"9.91","m":false,"R":false,"wt":"CONTRACT_PRICE","ot":"LIMIT",
+            "ps":"BOTH","cp":false,"rp":"0","pP":false,"si":0,"ss":0,
+            "V":"EXPIRE_TAKER","pm":"NONE","gtd":1568966400000}}"#,
+        );
+        let FutureUserStreamData::OrderTradeUpdate(update) = data else {
+            panic!("unexpected event {data:?}");
+        };
+        assert_eq!(update.order.time_in_force, TimeInForce::GTD);
+        assert_eq!(update.order.good_till_date, 1568966400000);
+        assert_eq!(update.order.order_status, OrderStatus::New);
+        assert_eq!(update.order.position_side, PositionSide::BOTH);
+        assert_eq!(
+            update.order.ask_notional,
+            Decimal::from_str("9.91").unwrap()
+        );
+        assert!(update.order.activation_price.is_none());
+    }
+
+    #[test]
+    fn account_update() {
+        let data = parse(
+            r#"{"e":"ACCOUNT_UPDATE","E":1564745798939,"T":1564745798938,"a":{"m":"ORDER",
+            "B":[{"a":"USDT","wb":"122624.12345678","cw":"100.12345678","bc":"50.12345678"}],
+            "P":[{"s":"BTCUSDT","pa":"-20","ep":"6563.66500","bep":"6563.6","cr":"0",
+            "up":"2850.21200","mt":"isolated","iw":"13200.70726908","ps":"SHORT"}]}}"#,
+        );
+        let FutureUserStreamData::AccountUpdate(update) = data else {
+            panic!("unexpected event {data:?}");
+        };
+        assert_eq!(update.data.reason, "ORDER");
+        assert_eq!(update.data.balances[0].asset, "USDT");
+        assert_eq!(update.data.positions[0].position_amt, Decimal::from(-20));
+        assert_eq!(update.data.positions[0].position_side, PositionSide::SHORT);
+    }
+
+    #[test]
+    fn margin_call_and_config_update() {
+        let data = parse(
+            r#"{"e":"MARGIN_CALL","E":1587727187525,"cw":"3.16812045","p":[{"s":"ETHUSDT",
+            "ps":"LONG","pa":"1.327","mt":"CROSSED","iw":"0","mp":"187.17127","up":"-1.166074",
+            "mm":"1.614445"}]}"#,
+        );
+        let FutureUserStreamData::MarginCall(call) = data else {
+            panic!("unexpected event {data:?}");
+        };
+        assert!(call.cross_wallet_balance.is_some());
+        assert_eq!(call.positions[0].symbol, "ETHUSDT");
+
+        let data = parse(
+            r#"{"e":"ACCOUNT_CONFIG_UPDATE","E":1611646737479,"T":1611646737476,
+            "ac":{"s":"BTCUSDT","l":25}}"#,
+        );
+        let FutureUserStreamData::AccountConfigUpdate(config) = data else {
+            panic!("unexpected event {data:?}");
+        };
+        assert_eq!(config.leverage.unwrap().leverage, 25);
+        assert!(config.multi_assets.is_none());
+    }
+
+    #[test]
+    fn trade_lite_and_listen_key_expired() {
+        let data = parse(
+            r#"{"e":"TRADE_LITE","E":1721895408092,"T":1721895408214,"s":"BTCUSDT",
+            "q":"0.001","p":"0","m":false,"c":"z8hcUoOsqEdKMeKPSABslD","S":"BUY",
+            "L":"64089.20","l":"0.040","t":109100866,"i":8886774}"#,
+        );
+        let FutureUserStreamData::TradeLite(trade) = data else {
+            panic!("unexpected event {data:?}");
+        };
+        assert_eq!(trade.side, OrderSide::Buy);
+        assert_eq!(trade.trade_id, 109100866);
+
+        let data = parse(r#"{"e":"listenKeyExpired","E":1576653824250,"listenKey":"abc"}"#);
+        let FutureUserStreamData::ListenKeyExpired(expired) = data else {
+            panic!("unexpected event {data:?}");
+        };
+        assert_eq!(expired.listen_key, "abc");
+    }
+}
@@ -1,13 +1,242 @@
-use std::ops::Not;
+use std::{ops::Not, sync::Arc, sync::OnceLock};
 
+use reqwest::header::HeaderMap;
 use serde::{Deserialize, Serialize};
 use tracing::warn;
 
 use crate::{
-    api::{common::ExchangeInfo, market::Kline},
+    api::{
+        ApiQuery, ApiResult, BinanceError, ClientError, ClockSync, ErrResponse, ParamWrapper,
+        QueryType, RateLimit, RateLimiter, RequestSigner, Response, SignError,
+        common::{ExchangeInfo, QServerTime},
+        market::Kline,
+    },
+    millis_ts,
     realtime_market::Interval,
 };
 
+pub const SPOT_REST_URL: &str = "https://api.binance.com";
+pub const TEST_SPOT_REST_URL: &str = "https://testnet.binance.vision";
+pub const FUTURE_REST_URL: &str = "https://fapi.binance.com";
+pub const TEST_FUTURE_REST_URL: &str = "https://testnet.binancefuture.com";
+
+/// REST 请求方法
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verb {
+    Get,
+    Post,
+    Put,
+    Delete,
+}
+
+impl From<Verb> for reqwest::Method {
+    fn from(value: Verb) -> Self {
+        match value {
+            Verb::Get => reqwest::Method::GET,
+            Verb::Post => reqwest::Method::POST,
+            Verb::Put => reqwest::Method::PUT,
+            Verb::Delete => reqwest::Method::DELETE,
+        }
+    }
+}
+
+/// 可以通过 REST 接口发送的请求, 参数和响应与 WebSocket API 相同
+pub trait RestQuery: ApiQuery + Clone {
+    /// 接口路径, 如 `/api/v3/order`
+    const PATH: &'static str;
+    const VERB: Verb = Verb::Get;
+}
+
+/// REST 客户端, 所有请求共用一个连接池, 可以在多个线程间共享
+///
+/// 签名请求需要 [`RestClient::with_signer`], api key 通过 `X-MBX-APIKEY` 请求头发送
+pub struct RestClient {
+    http: reqwest::blocking::Client,
+    base_url: String,
+    recv_window: i64,
+    limiter: Option<Arc<RateLimiter>>,
+    clock: Arc<ClockSync>,
+    signer: Option<RequestSigner>,
+}
+
+impl RestClient {
+    /// `base_url` 如 [`SPOT_REST_URL`], [`FUTURE_REST_URL`]
+    pub fn new(base_url: &str, recv_window: i64) -> Self {
+        Self {
+            http: http_client().clone(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            recv_window,
+            limiter: None,
+            clock: Default::default(),
+            signer: None,
+        }
+    }
+
+    /// 使用自定义的 http 客户端, 如设置代理或超时
+    pub fn with_http_client(mut self, http: reqwest::blocking::Client) -> Self {
+        self.http = http;
+        self
+    }
+
+    /// 使用校准后的时间签名, 多个客户端可以共享同一个时钟
+    pub fn with_clock(mut self, clock: Arc<ClockSync>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn clock(&self) -> &Arc<ClockSync> {
+        &self.clock
+    }
+
+    /// 立即校准时间, 返回新的偏移(ms)
+    pub fn sync_clock(&self) -> Result<i64, ClientError> {
+        self.clock
+            .sync(|| self.query(QServerTime).map(|resp| resp.result.time))
+    }
+
+    /// 发送请求前检查限频, 多个客户端可以共享同一个限频器
+    pub fn with_rate_limiter(mut self, limiter: Arc<RateLimiter>) -> Self {
+        self.limiter = Some(limiter);
+        self
+    }
+
+    pub fn with_signer(mut self, signer: RequestSigner) -> Self {
+        self.signer = Some(signer);
+        self
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    pub fn query<P: RestQuery>(&self, param: P) -> ApiResult<P::Response> {
+        if P::TYPE != QueryType::None
+            && self.clock.need_refresh()
+            && let Err(e) = self.sync_clock()
+        {
+            warn!("failed to sync clock {e}");
+        }
+        let permit = match &self.limiter {
+            Some(limiter) => Some(limiter.acquire_for(&param)?),
+            None => None,
+        };
+        let resp = self.send(param);
+        if let (Some(limiter), Some(permit)) = (&self.limiter, permit) {
+            limiter.observe_for(permit, &resp);
+        }
+        self.clock.observe(&resp);
+        resp
+    }
+
+    fn send<P: RestQuery>(&self, param: P) -> ApiResult<P::Response> {
+        let signed = P::TYPE != QueryType::None;
+        let param = ParamWrapper {
+            recv_window: signed.then_some(self.recv_window),
+            api_key: None,
+            timestamp: if signed { self.clock.now() } else { 0 },
+            signature: None,
+            other: param,
+        };
+        let mut api_key = self.signer.as_ref().map(|s| s.api_key.clone());
+        let mut pairs = vec![];
+        for (k, v) in param.params()? {
+            // REST 接口的 api key 放在请求头中
+            if k == "apiKey" {
+                api_key = Some(v);
+            } else {
+                pairs.push((k, v));
+            }
+        }
+        let mut query = encode_query(&pairs);
+        if signed {
+            let signer = self.signer.as_ref().ok_or(SignError::MissingSigner)?;
+            let signature = signer.signer.sign(query.as_bytes());
+            if !query.is_empty() {
+                query.push('&');
+            }
+            query.push_str(&encode_query(&[("signature".into(), signature)]));
+        }
+        let mut url = format!("{}{}", self.base_url, P::PATH);
+        if !query.is_empty() {
+            url = format!("{url}?{query}");
+        }
+        tracing::debug!("{:?} {url}", P::VERB);
+        let mut req = self.http.request(P::VERB.into(), url);
+        if let Some(api_key) = api_key {
+            req = req.header("X-MBX-APIKEY", api_key);
+        }
+        let resp = req.send()?;
+        let status = resp.status();
+        let rate_limits = header_rate_limits(resp.headers());
+        let retry_after = resp
+            .headers()
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<i64>().ok());
+        let body = resp.text()?;
+        if status.is_success().not() {
+            let mut error = serde_json::from_str::<BinanceError>(&body).unwrap_or(BinanceError {
+                code: status.as_u16() as i64,
+                msg: body,
+                data: None,
+            });
+            if let Some(secs) = retry_after {
+                error.data = Some(serde_json::json!({ "retryAfter": millis_ts() + secs * 1000 }));
+            }
+            return Err(ClientError::ApiError(ErrResponse {
+                id: 0,
+                error,
+                status: status.as_u16() as i64,
+                rate_limits,
+            }));
+        }
+        Ok(Response {
+            id: 0,
+            result: serde_json::from_str(&body)?,
+            rate_limits,
+        })
+    }
+}
+
+fn encode_query(pairs: &[(String, String)]) -> String {
+    url::form_urlencoded::Serializer::new(String::new())
+        .extend_pairs(pairs)
+        .finish()
+}
+
+/// 从 `X-MBX-USED-WEIGHT-1M`, `X-MBX-ORDER-COUNT-10S` 等响应头解析当前计数
+fn header_rate_limits(headers: &HeaderMap) -> Vec<RateLimit> {
+    headers
+        .iter()
+        .filter_map(|(name, value)| {
+            let name = name.as_str();
+            let (ty, window) = if let Some(w) = name.strip_prefix("x-mbx-used-weight-") {
+                ("REQUEST_WEIGHT", w)
+            } else if let Some(w) = name.strip_prefix("x-mbx-order-count-") {
+                ("ORDERS", w)
+            } else {
+                return None;
+            };
+            let (num, unit) = window.split_at(window.len().checked_sub(1)?);
+            let interval = match unit {
+                "s" => "SECOND",
+                "m" => "MINUTE",
+                "h" => "HOUR",
+                "d" => "DAY",
+                _ => return None,
+            };
+            Some(RateLimit {
+                rate_limit_type: ty.to_string(),
+                interval: interval.to_string(),
+                interval_num: num.parse().ok()?,
+                limit: 0,
+                count: value.to_str().ok()?.parse().ok()?,
+            })
+        })
+        .collect()
+}
+
+/// 合约K线
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 #[serde(rename_all = "camelCase")]
 pub struct QKline {
@@ -21,47 +250,74 @@ pub struct QKline {
     pub limit: Option<i64>,
 }
 
+impl ApiQuery for QKline {
+    type Response = Vec<Kline>;
+    const METHOD: &'static str = "klines";
+    const TYPE: QueryType = QueryType::None;
+
+    fn weight(&self) -> u32 {
+        match self.limit.unwrap_or(500) {
+            ..100 => 1,
+            100..500 => 2,
+            500..=1000 => 5,
+            _ => 10,
+        }
+    }
+}
+
+impl RestQuery for QKline {
+    const PATH: &'static str = "/fapi/v1/klines";
+}
+
 fn init_http_client() -> reqwest::blocking::Client {
     let mut builder = reqwest::blocking::Client::builder();
     if let Ok(p) = std::env::var("HTTPS_PROXY") {
-        builder = builder.proxy(reqwest::Proxy::https(p).unwrap())
+        match reqwest::Proxy::https(&p) {
+            Ok(proxy) => builder = builder.proxy(proxy),
+            Err(e) => warn!("invalid HTTPS_PROXY {p}: {e}"),
+        }
     }
-    builder.build().unwrap()
+    builder.build().unwrap_or_else(|e| {
+        warn!("failed to build http client {e}, fallback to default");
+        reqwest::blocking::Client::new()
+    })
+}
+
+/// 进程内共用的 http 客户端
+fn http_client() -> &'static reqwest::blocking::Client {
+    static CLIENT: OnceLock<reqwest::blocking::Client> = OnceLock::new();
+    CLIENT.get_or_init(init_http_client)
+}
+
+fn io_error(e: impl ToString) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string())
 }
 
 pub fn future_kline_line(param: QKline) -> std::io::Result<Vec<Kline>> {
-    let client = init_http_client();
-    let qs_str = serde_qs::to_string(&param).unwrap();
-    let resp = client
-        .get(format!("https://fapi.binance.com/fapi/v1/klines?{qs_str}"))
+    let qs_str = serde_qs::to_string(&param).map_err(io_error)?;
+    let resp = http_client()
+        .get(format!("{FUTURE_REST_URL}/fapi/v1/klines?{qs_str}"))
         .send()
-        .unwrap();
+        .map_err(std::io::Error::other)?;
     if resp.status().is_success().not() {
-        warn!("{:?}", resp.text());
-        return Ok(vec![]);
+        let resp = resp.text().map_err(std::io::Error::other)?;
+        warn!("{:?}", resp);
+        return Err(io_error(resp));
     }
-    let data = resp
-        .json()
-        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string()))?;
+    let data = resp.json().map_err(io_error)?;
     Ok(data)
 }
 
 pub fn exchange_info() -> std::io::Result<ExchangeInfo> {
-    let client = init_http_client();
-    let resp = client
-        .get(format!("https://fapi.binance.com/fapi/v1/exchangeInfo"))
+    let resp = http_client()
+        .get(format!("{FUTURE_REST_URL}/fapi/v1/exchangeInfo"))
         .send()
-        .unwrap();
+        .map_err(std::io::Error::other)?;
     if resp.status().is_success().not() {
-        let resp = resp.text();
+        let resp = resp.text().map_err(std::io::Error::other)?;
         warn!("{:?}", resp);
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::InvalidData,
-            resp.unwrap().to_string(),
-        ));
+        return Err(io_error(resp));
     }
-    let data = resp
-        .json()
-        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string()))?;
+    let data = resp.json().map_err(io_error)?;
     Ok(data)
 }
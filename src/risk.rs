@@ -0,0 +1,394 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use rust_decimal::Decimal;
+use tracing::{debug, info, warn};
+
+use crate::{
+    OrderSide,
+    api::{
+        ClientError, ErrorCode, Response,
+        market::{QFutureLatestPrice, QLatestPrice},
+        trade::{
+            CancelOpenOrders, CreateOrderResponse, FutureCancelOpenOrders, FutureOrderResult,
+            FutureOrderSpec, OrderSpec, QPositionRisk,
+        },
+    },
+    executor::Executor,
+    realtime_market::{BookTickerEvent, ExecutionReport, FutureOrderUpdate},
+    symbol_registry::{FilterError, OrderFields, SymbolRegistry},
+};
+
+/// 风控拒绝下单的原因
+#[derive(Debug, thiserror::Error)]
+pub enum RiskError {
+    #[error("kill switch is on")]
+    KillSwitch,
+    #[error("{}", fmt_filter_errors(.0))]
+    Filter(Vec<FilterError>),
+    #[error("notional {notional} over limit {max}")]
+    MaxNotional { notional: Decimal, max: Decimal },
+    #[error("{symbol} position {position} would exceed limit {max}")]
+    MaxPosition {
+        symbol: String,
+        position: Decimal,
+        max: Decimal,
+    },
+    #[error("price {price} outside {band} band around {reference}")]
+    PriceBand {
+        price: Decimal,
+        reference: Decimal,
+        band: Decimal,
+    },
+    #[error("no reference price for {0}")]
+    NoReferencePrice(String),
+    #[error("{open} open orders placed through guard reach limit {max}")]
+    MaxGuardedOrders { open: usize, max: usize },
+    #[error("{0}")]
+    ClientError(ClientError),
+}
+
+impl From<ClientError> for RiskError {
+    fn from(value: ClientError) -> Self {
+        Self::ClientError(value)
+    }
+}
+
+/// 撤销所有挂单时没有挂单返回 -2011
+fn is_no_open_orders(e: &ClientError, is_future: bool) -> bool {
+    e.api_error().is_some_and(|e| {
+        let code = match is_future {
+            true => e.future_error_code(),
+            false => e.error_code(),
+        };
+        code == ErrorCode::CancelRejected
+    })
+}
+
+fn fmt_filter_errors(errors: &[FilterError]) -> String {
+    errors
+        .iter()
+        .map(|e| e.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// 风控限制, 为 `None` 时不检查
+#[derive(Debug, Clone, Default)]
+pub struct RiskLimits {
+    /// 单笔订单最大名义价值(报价资产)
+    pub max_notional: Option<Decimal>,
+    /// 每个交易对的最大净持仓(标的资产), 仅合约
+    pub max_position: Option<Decimal>,
+    /// 按交易对覆盖 `max_position`
+    pub symbol_max_position: HashMap<String, Decimal>,
+    /// 限价相对参考价的最大偏离比例, 如 0.05 表示 ±5%
+    pub price_band: Option<Decimal>,
+    /// 通过风控下单且未结束的最大订单数, 不包括其他途径下的挂单
+    pub max_guarded_orders: Option<usize>,
+}
+
+impl RiskLimits {
+    fn max_position(&self, symbol: &str) -> Option<Decimal> {
+        self.symbol_max_position
+            .get(symbol)
+            .copied()
+            .or(self.max_position)
+    }
+}
+
+/// 在下单前做风控检查的客户端包装
+///
+/// 参考价取 [`RiskGuard::on_book_ticker`] 推送的买卖一中间价, 或 [`RiskGuard::set_price`] 设置的价格,
+/// 超过 `price_ttl` 未更新时用 [`QLatestPrice`] 或 [`QFutureLatestPrice`] 查询. 订单结束需要通过
+/// [`RiskGuard::on_execution_report`] 或 [`RiskGuard::on_future_order_update`] 通知,
+/// 否则会一直计入挂单数量. 挂单数量只统计通过风控下的订单, 不会从交易所同步
+pub struct RiskGuard<E> {
+    executor: E,
+    limits: RiskLimits,
+    registry: Option<SymbolRegistry>,
+    price_ttl: Duration,
+    prices: HashMap<String, (Decimal, Instant)>,
+    /// 通过风控下单且未结束的订单, 按 (是否合约, 订单ID) 记录交易对
+    open_orders: HashMap<(bool, i64), String>,
+    /// 通过风控下过单的 (是否合约, 交易对), [`RiskGuard::kill`] 撤销这些交易对的挂单
+    symbols: HashSet<(bool, String)>,
+    killed: Arc<AtomicBool>,
+}
+
+impl<E: Executor> RiskGuard<E> {
+    pub fn new(executor: E, limits: RiskLimits) -> Self {
+        Self {
+            executor,
+            limits,
+            registry: None,
+            price_ttl: Duration::from_secs(10),
+            prices: Default::default(),
+            open_orders: Default::default(),
+            symbols: Default::default(),
+            killed: Default::default(),
+        }
+    }
+
+    /// 下单前同时检查交易对的交易规则
+    pub fn with_registry(mut self, registry: SymbolRegistry) -> Self {
+        self.registry = Some(registry);
+        self
+    }
+
+    pub fn with_price_ttl(mut self, ttl: Duration) -> Self {
+        self.price_ttl = ttl;
+        self
+    }
+
+    pub fn limits(&self) -> &RiskLimits {
+        &self.limits
+    }
+
+    pub fn limits_mut(&mut self) -> &mut RiskLimits {
+        &mut self.limits
+    }
+
+    /// 发送不需要风控的请求, 如查询
+    pub fn executor(&mut self) -> &mut E {
+        &mut self.executor
+    }
+
+    /// 开关句柄, 可以在其他线程中设置为 `true` 阻止下单, 但不会撤单
+    pub fn kill_switch(&self) -> Arc<AtomicBool> {
+        self.killed.clone()
+    }
+
+    pub fn is_killed(&self) -> bool {
+        self.killed.load(Ordering::Relaxed)
+    }
+
+    /// 阻止新的订单并撤销通过风控下过单的交易对的所有挂单, 返回撤单失败的错误
+    ///
+    /// 这些交易对上不是通过风控下的挂单也会被撤销, 但不会处理其他交易对,
+    /// 不能作为整个账户的紧急开关
+    pub fn kill(&mut self) -> Vec<ClientError> {
+        self.killed.store(true, Ordering::Relaxed);
+        warn!(
+            "kill switch on, cancel open orders of {} symbols",
+            self.symbols.len()
+        );
+        let mut errors = vec![];
+        for (is_future, symbol) in std::mem::take(&mut self.symbols) {
+            let resp = match is_future {
+                true => self
+                    .executor
+                    .execute(FutureCancelOpenOrders {
+                        symbol: symbol.clone(),
+                    })
+                    .map(|_| ()),
+                false => self
+                    .executor
+                    .execute(CancelOpenOrders {
+                        symbol: symbol.clone(),
+                    })
+                    .map(|_| ()),
+            };
+            match resp {
+                Ok(()) => {}
+                // 交易对上已经没有挂单
+                Err(e) if is_no_open_orders(&e, is_future) => {
+                    debug!("no open orders of {symbol}")
+                }
+                Err(e) => {
+                    warn!("cancel {symbol} open orders failed {e}");
+                    // 保留交易对, 再次调用时重试
+                    self.symbols.insert((is_future, symbol));
+                    errors.push(e);
+                    continue;
+                }
+            }
+            self.open_orders
+                .retain(|(future, _), s| *future != is_future || *s != symbol);
+        }
+        errors
+    }
+
+    /// 关闭开关, 恢复下单
+    pub fn resume(&mut self) {
+        info!("kill switch off");
+        self.killed.store(false, Ordering::Relaxed);
+    }
+
+    /// 通过风控下单且未结束的订单数
+    pub fn guarded_orders(&self) -> usize {
+        self.open_orders.len()
+    }
+
+    pub fn set_price(&mut self, symbol: &str, price: Decimal) {
+        self.prices
+            .insert(symbol.to_string(), (price, Instant::now()));
+    }
+
+    pub fn on_book_ticker(&mut self, event: &BookTickerEvent) {
+        let mid = (event.buy_price + event.sell_price) / Decimal::TWO;
+        self.set_price(&event.symbol, mid);
+    }
+
+    pub fn on_execution_report(&mut self, report: &ExecutionReport) {
+        if report.order_status.is_final() {
+            self.open_orders.remove(&(false, report.order_id));
+        }
+    }
+
+    pub fn on_future_order_update(&mut self, update: &FutureOrderUpdate) {
+        if update.order_status.is_final() {
+            self.open_orders.remove(&(true, update.order_id));
+        }
+    }
+
+    pub fn place_order(
+        &mut self,
+        order: OrderSpec,
+    ) -> Result<Response<CreateOrderResponse>, RiskError> {
+        self.check(&OrderFields::from(&order), false)?;
+        // 下单失败时订单也可能已经创建
+        self.symbols.insert((false, order.symbol.clone()));
+        let resp = self.executor.execute(order.clone())?;
+        if !resp.result.status().is_some_and(|s| s.is_final()) {
+            self.track(resp.result.order_id(), &order.symbol, false);
+        }
+        Ok(resp)
+    }
+
+    pub fn place_future_order(
+        &mut self,
+        order: FutureOrderSpec,
+    ) -> Result<Response<FutureOrderResult>, RiskError> {
+        self.check(&OrderFields::from(&order), true)?;
+        self.symbols.insert((true, order.symbol.clone()));
+        let resp = self.executor.execute(order.clone())?;
+        if !resp.result.status.is_final() {
+            self.track(resp.result.order_id, &order.symbol, true);
+        }
+        Ok(resp)
+    }
+
+    fn track(&mut self, order_id: i64, symbol: &str, is_future: bool) {
+        self.open_orders
+            .insert((is_future, order_id), symbol.to_string());
+    }
+
+    fn check(&mut self, order: &OrderFields, is_future: bool) -> Result<(), RiskError> {
+        self.check_inner(order, is_future).inspect_err(|e| {
+            warn!("reject {} {:?} order: {e}", order.symbol, order.side);
+        })
+    }
+
+    fn check_inner(&mut self, order: &OrderFields, is_future: bool) -> Result<(), RiskError> {
+        if self.is_killed() {
+            return Err(RiskError::KillSwitch);
+        }
+        let price = order.price.filter(|_| !order.is_market);
+        let need_reference = (price.is_some() && self.limits.price_band.is_some())
+            || (price.is_none() && self.limits.max_notional.is_some());
+        let reference = match need_reference {
+            true => Some(
+                self.reference_price(&order.symbol, is_future)
+                    .ok_or_else(|| RiskError::NoReferencePrice(order.symbol.clone()))?,
+            ),
+            false => None,
+        };
+        if let Some(registry) = &self.registry {
+            registry
+                .validate(order.clone(), reference)
+                .map_err(RiskError::Filter)?;
+        }
+        if let Some(max) = self.limits.max_notional {
+            let notional = match (price.or(reference), order.quantity) {
+                (Some(price), Some(qty)) => Some(price * qty),
+                _ => order.quote_order_qty,
+            };
+            if let Some(notional) = notional
+                && notional > max
+            {
+                return Err(RiskError::MaxNotional { notional, max });
+            }
+        }
+        if let (Some(band), Some(price), Some(reference)) =
+            (self.limits.price_band, price, reference)
+            && (price - reference).abs() > reference * band
+        {
+            return Err(RiskError::PriceBand {
+                price,
+                reference,
+                band,
+            });
+        }
+        if let Some(max) = self.limits.max_guarded_orders
+            && self.open_orders.len() >= max
+        {
+            return Err(RiskError::MaxGuardedOrders {
+                open: self.open_orders.len(),
+                max,
+            });
+        }
+        if let (true, Some(max), Some(qty)) = (
+            is_future && !order.reduce_only,
+            self.limits.max_position(&order.symbol),
+            order.quantity,
+        ) {
+            let current: Decimal = self
+                .executor
+                .execute(QPositionRisk {
+                    symbol: Some(order.symbol.clone()),
+                })?
+                .result
+                .iter()
+                .map(|p| p.position_amt)
+                .sum();
+            let position = match order.side {
+                OrderSide::Buy => current + qty,
+                OrderSide::Sell => current - qty,
+            };
+            // 减仓方向的订单不受限制
+            if position.abs() > max && position.abs() > current.abs() {
+                return Err(RiskError::MaxPosition {
+                    symbol: order.symbol.clone(),
+                    position,
+                    max,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// 未过期的缓存价格, 否则查询最新价
+    fn reference_price(&mut self, symbol: &str, is_future: bool) -> Option<Decimal> {
+        if let Some((price, at)) = self.prices.get(symbol)
+            && at.elapsed() < self.price_ttl
+        {
+            return Some(*price);
+        }
+        let resp = match is_future {
+            true => self.executor.execute(QFutureLatestPrice {
+                symbol: Some(symbol.to_string()),
+            }),
+            false => self
+                .executor
+                .execute(QLatestPrice::Symbol(symbol.to_string())),
+        };
+        match resp {
+            Ok(resp) => {
+                let price = resp.result.to_list().first()?.price;
+                self.set_price(symbol, price);
+                Some(price)
+            }
+            Err(e) => {
+                warn!("query {symbol} latest price failed {e}");
+                None
+            }
+        }
+    }
+}
@@ -0,0 +1,667 @@
+use std::collections::HashMap;
+
+use rust_decimal::Decimal;
+
+use crate::{
+    FutureOrderType, OrderSide, OrderType,
+    api::{
+        common::{ExSymbol, ExchangeInfo, SymbolFilter},
+        trade::{FutureOrderSpec, OrderSpec},
+    },
+};
+
+/// 按步长取整的方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Rounding {
+    /// 向下取整
+    #[default]
+    Floor,
+    /// 向上取整
+    Ceil,
+    /// 四舍五入
+    Nearest,
+}
+
+/// 订单违反的交易规则
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum FilterError {
+    #[error("unknown symbol {0}")]
+    UnknownSymbol(String),
+    #[error("price is required for limit order")]
+    MissingPrice,
+    #[error("quantity is required")]
+    MissingQuantity,
+    #[error("price {price} out of range [{min}, {max}]")]
+    PriceRange {
+        price: Decimal,
+        min: Decimal,
+        max: Decimal,
+    },
+    #[error("price {price} is not a multiple of tick size {tick_size}")]
+    TickSize { price: Decimal, tick_size: Decimal },
+    #[error("price {price} out of percent range [{min}, {max}] of {reference}")]
+    PercentPrice {
+        price: Decimal,
+        reference: Decimal,
+        min: Decimal,
+        max: Decimal,
+    },
+    #[error("quantity {qty} out of range [{min}, {max}]")]
+    QtyRange {
+        qty: Decimal,
+        min: Decimal,
+        max: Decimal,
+    },
+    #[error("quantity {qty} is not a multiple of step size {step_size}")]
+    StepSize { qty: Decimal, step_size: Decimal },
+    #[error("notional {notional} below minimum {min}")]
+    MinNotional { notional: Decimal, min: Decimal },
+    #[error("notional {notional} above maximum {max}")]
+    MaxNotional { notional: Decimal, max: Decimal },
+    #[error("iceberg parts {parts} over limit {limit}")]
+    IcebergParts { parts: Decimal, limit: usize },
+    #[error("trailing delta {delta} out of range [{min}, {max}]")]
+    TrailingDelta { delta: u64, min: i64, max: i64 },
+}
+
+/// 校验交易规则需要的订单字段
+#[derive(Debug, Clone, Default)]
+pub struct OrderFields {
+    pub symbol: String,
+    pub side: OrderSide,
+    /// 市价单, 价格取参考价, 适用 `MARKET_LOT_SIZE`
+    pub is_market: bool,
+    /// 限价单需要价格
+    pub need_price: bool,
+    pub price: Option<Decimal>,
+    pub stop_price: Option<Decimal>,
+    pub quantity: Option<Decimal>,
+    pub quote_order_qty: Option<Decimal>,
+    pub iceberg_qty: Option<Decimal>,
+    pub trailing_delta: Option<u64>,
+    /// 追踪止损的触发方向是否在当前价格之上, 用于 `TRAILING_DELTA`
+    pub trailing_above: Option<bool>,
+    /// 只减仓或平仓单, 不检查最小名义价值和数量
+    pub reduce_only: bool,
+}
+
+impl From<&OrderSpec> for OrderFields {
+    fn from(value: &OrderSpec) -> Self {
+        let stop_loss = matches!(
+            value.order_type,
+            OrderType::StopLoss | OrderType::StopLossLimit
+        );
+        Self {
+            symbol: value.symbol.clone(),
+            side: value.side,
+            is_market: matches!(
+                value.order_type,
+                OrderType::Market | OrderType::StopLoss | OrderType::TakeProfit
+            ),
+            need_price: matches!(
+                value.order_type,
+                OrderType::Limit
+                    | OrderType::LimitMaker
+                    | OrderType::StopLossLimit
+                    | OrderType::TakeProfitLimit
+            ),
+            price: value.price,
+            stop_price: value.stop_price,
+            quantity: value.quantity,
+            quote_order_qty: value.quote_order_qty,
+            iceberg_qty: value.iceberg_qty,
+            trailing_delta: value.trailing_delta,
+            // 买入止损和卖出止盈在价格上涨时触发
+            trailing_above: Some(stop_loss == (value.side == OrderSide::Buy)),
+            reduce_only: false,
+        }
+    }
+}
+
+impl From<&FutureOrderSpec> for OrderFields {
+    fn from(value: &FutureOrderSpec) -> Self {
+        Self {
+            symbol: value.symbol.clone(),
+            side: value.side,
+            is_market: !matches!(
+                value.order_type,
+                FutureOrderType::Limit | FutureOrderType::Stop | FutureOrderType::TakeProfit
+            ),
+            need_price: matches!(
+                value.order_type,
+                FutureOrderType::Limit | FutureOrderType::Stop | FutureOrderType::TakeProfit
+            ),
+            price: value.price,
+            stop_price: value.stop_price,
+            quantity: value.quantity,
+            quote_order_qty: value.quote_order_qty,
+            iceberg_qty: value.iceberg_qty,
+            trailing_delta: None,
+            trailing_above: None,
+            reduce_only: value.reduce_only.unwrap_or_default()
+                || value.close_position.unwrap_or_default(),
+        }
+    }
+}
+
+/// 单个交易对的交易规则
+#[derive(Debug, Clone)]
+pub struct SymbolRules {
+    pub symbol: String,
+    pub base_asset: String,
+    pub quote_asset: String,
+    pub filters: Vec<SymbolFilter>,
+}
+
+impl From<&ExSymbol> for SymbolRules {
+    fn from(value: &ExSymbol) -> Self {
+        Self {
+            symbol: value.symbol.clone(),
+            base_asset: value.base_asset.clone(),
+            quote_asset: value.quote_asset.clone(),
+            filters: value.filters.clone(),
+        }
+    }
+}
+
+impl SymbolRules {
+    /// `PRICE_FILTER` 的最小价格和价格步长
+    fn price_filter(&self) -> Option<(Decimal, Decimal)> {
+        self.filters.iter().find_map(|f| match f {
+            SymbolFilter::PriceFilter {
+                min_price,
+                tick_size,
+                ..
+            } => Some((*min_price, *tick_size)),
+            _ => None,
+        })
+    }
+
+    /// `LOT_SIZE` 的最小数量和数量步长, 市价单优先使用 `MARKET_LOT_SIZE`
+    fn lot_size(&self, is_market: bool) -> Option<(Decimal, Decimal)> {
+        let market = self.filters.iter().find_map(|f| match f {
+            SymbolFilter::MarketLOTSize {
+                step_size, min_qty, ..
+            } if is_market && !step_size.is_zero() => Some((*min_qty, *step_size)),
+            _ => None,
+        });
+        market.or_else(|| {
+            self.filters.iter().find_map(|f| match f {
+                SymbolFilter::LOTSize {
+                    step_size, min_qty, ..
+                } => Some((*min_qty, *step_size)),
+                _ => None,
+            })
+        })
+    }
+
+    pub fn tick_size(&self) -> Option<Decimal> {
+        self.price_filter().map(|(_, tick)| tick)
+    }
+
+    pub fn step_size(&self) -> Option<Decimal> {
+        self.lot_size(false).map(|(_, step)| step)
+    }
+
+    /// 按价格步长取整
+    pub fn round_price(&self, price: Decimal, rounding: Rounding) -> Decimal {
+        match self.price_filter() {
+            Some((min, tick)) => round_step(price, min, tick, rounding),
+            None => price,
+        }
+    }
+
+    /// 按数量步长取整, 市价单使用 `MARKET_LOT_SIZE` 的步长
+    pub fn round_qty(&self, qty: Decimal, is_market: bool, rounding: Rounding) -> Decimal {
+        match self.lot_size(is_market) {
+            Some((min, step)) => round_step(qty, min, step, rounding),
+            None => qty,
+        }
+    }
+
+    /// 检查订单是否满足所有交易规则, 返回所有违反的规则
+    ///
+    /// `reference` 为市价单估算名义价值和百分比价格规则使用的参考价(如最新价或标记价格),
+    /// 为 `None` 时跳过这些检查. 最大挂单数量和最大持仓等依赖账户状态的规则不在这里检查
+    pub fn validate(
+        &self,
+        order: &OrderFields,
+        reference: Option<Decimal>,
+    ) -> Result<(), Vec<FilterError>> {
+        let mut errors = vec![];
+        if order.need_price && order.price.is_none() {
+            errors.push(FilterError::MissingPrice);
+        }
+        if order.quantity.is_none() && order.quote_order_qty.is_none() && !order.reduce_only {
+            errors.push(FilterError::MissingQuantity);
+        }
+        let price = order.price.filter(|_| !order.is_market);
+        let notional = match (price.or(reference), order.quantity) {
+            (Some(price), Some(qty)) => Some(price * qty),
+            _ => order.quote_order_qty,
+        };
+        for filter in &self.filters {
+            match filter {
+                SymbolFilter::PriceFilter {
+                    min_price,
+                    max_price,
+                    tick_size,
+                } => {
+                    for price in [price, order.stop_price].into_iter().flatten() {
+                        if (!min_price.is_zero() && price < *min_price)
+                            || (!max_price.is_zero() && price > *max_price)
+                        {
+                            errors.push(FilterError::PriceRange {
+                                price,
+                                min: *min_price,
+                                max: *max_price,
+                            });
+                        }
+                        if !is_multiple(price, *min_price, *tick_size) {
+                            errors.push(FilterError::TickSize {
+                                price,
+                                tick_size: *tick_size,
+                            });
+                        }
+                    }
+                }
+                SymbolFilter::PercentPrice {
+                    multiplier_down,
+                    multiplier_up,
+                    ..
+                } => {
+                    if let (Some(price), Some(reference)) = (price, reference) {
+                        check_percent(
+                            &mut errors,
+                            price,
+                            reference,
+                            *multiplier_down,
+                            *multiplier_up,
+                        );
+                    }
+                }
+                SymbolFilter::PercentPriceBySide {
+                    bid_multiplier_up,
+                    bid_multiplier_down,
+                    ask_multiplier_up,
+                    ask_multiplier_down,
+                    ..
+                } => {
+                    if let (Some(price), Some(reference)) = (price, reference) {
+                        let (down, up) = match order.side {
+                            OrderSide::Buy => (*bid_multiplier_down, *bid_multiplier_up),
+                            OrderSide::Sell => (*ask_multiplier_down, *ask_multiplier_up),
+                        };
+                        check_percent(&mut errors, price, reference, down, up);
+                    }
+                }
+                SymbolFilter::LOTSize {
+                    step_size,
+                    max_qty,
+                    min_qty,
+                } => check_qty(&mut errors, order, *min_qty, *max_qty, *step_size),
+                SymbolFilter::MarketLOTSize {
+                    step_size,
+                    max_qty,
+                    min_qty,
+                } if order.is_market => {
+                    check_qty(&mut errors, order, *min_qty, *max_qty, *step_size)
+                }
+                SymbolFilter::MinNotional { notional: min } => {
+                    if let Some(notional) = notional
+                        && !order.reduce_only
+                        && notional < *min
+                    {
+                        errors.push(FilterError::MinNotional {
+                            notional,
+                            min: *min,
+                        });
+                    }
+                }
+                SymbolFilter::Notional {
+                    min_notional,
+                    apply_min_to_market,
+                    max_notional,
+                    apply_max_to_market,
+                    ..
+                } => {
+                    let Some(notional) = notional else {
+                        continue;
+                    };
+                    if (!order.is_market || *apply_min_to_market)
+                        && !order.reduce_only
+                        && notional < *min_notional
+                    {
+                        errors.push(FilterError::MinNotional {
+                            notional,
+                            min: *min_notional,
+                        });
+                    }
+                    if (!order.is_market || *apply_max_to_market)
+                        && !max_notional.is_zero()
+                        && notional > *max_notional
+                    {
+                        errors.push(FilterError::MaxNotional {
+                            notional,
+                            max: *max_notional,
+                        });
+                    }
+                }
+                SymbolFilter::IcebergParts { limit } => {
+                    if let (Some(qty), Some(iceberg)) = (order.quantity, order.iceberg_qty)
+                        && !iceberg.is_zero()
+                    {
+                        let parts = (qty / iceberg).ceil();
+                        if parts > Decimal::from(*limit) {
+                            errors.push(FilterError::IcebergParts {
+                                parts,
+                                limit: *limit,
+                            });
+                        }
+                    }
+                }
+                SymbolFilter::TrailingDelta {
+                    min_trailing_above_delta,
+                    max_trailing_above_delta,
+                    min_trailing_below_delta,
+                    max_trailing_below_delta,
+                } => {
+                    if let (Some(delta), Some(above)) = (order.trailing_delta, order.trailing_above)
+                    {
+                        let (min, max) = match above {
+                            true => (*min_trailing_above_delta, *max_trailing_above_delta),
+                            false => (*min_trailing_below_delta, *max_trailing_below_delta),
+                        };
+                        if (delta as i64) < min || (delta as i64) > max {
+                            errors.push(FilterError::TrailingDelta { delta, min, max });
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        match errors.is_empty() {
+            true => Ok(()),
+            false => Err(errors),
+        }
+    }
+}
+
+/// 所有交易对的交易规则, 由 [`ExchangeInfo`] 构建
+#[derive(Debug, Clone, Default)]
+pub struct SymbolRegistry {
+    symbols: HashMap<String, SymbolRules>,
+}
+
+impl From<&ExchangeInfo> for SymbolRegistry {
+    fn from(value: &ExchangeInfo) -> Self {
+        let mut registry = Self::default();
+        registry.update(value);
+        registry
+    }
+}
+
+impl SymbolRegistry {
+    /// 用新的 [`ExchangeInfo`] 更新, 保留其中没有的交易对
+    pub fn update(&mut self, info: &ExchangeInfo) {
+        for symbol in &info.symbols {
+            self.symbols
+                .insert(symbol.symbol.clone(), SymbolRules::from(symbol));
+        }
+    }
+
+    pub fn get(&self, symbol: &str) -> Option<&SymbolRules> {
+        self.symbols.get(symbol)
+    }
+
+    pub fn symbols(&self) -> impl Iterator<Item = &str> {
+        self.symbols.keys().map(String::as_str)
+    }
+
+    fn rules(&self, symbol: &str) -> Result<&SymbolRules, FilterError> {
+        self.get(symbol)
+            .ok_or_else(|| FilterError::UnknownSymbol(symbol.to_string()))
+    }
+
+    pub fn round_price(
+        &self,
+        symbol: &str,
+        price: Decimal,
+        rounding: Rounding,
+    ) -> Result<Decimal, FilterError> {
+        Ok(self.rules(symbol)?.round_price(price, rounding))
+    }
+
+    pub fn round_qty(
+        &self,
+        symbol: &str,
+        qty: Decimal,
+        is_market: bool,
+        rounding: Rounding,
+    ) -> Result<Decimal, FilterError> {
+        Ok(self.rules(symbol)?.round_qty(qty, is_market, rounding))
+    }
+
+    /// 发送前检查 [`OrderSpec`] 或 [`FutureOrderSpec`], 见 [`SymbolRules::validate`]
+    pub fn validate(
+        &self,
+        order: impl Into<OrderFields>,
+        reference: Option<Decimal>,
+    ) -> Result<(), Vec<FilterError>> {
+        let order = order.into();
+        self.rules(&order.symbol)
+            .map_err(|e| vec![e])?
+            .validate(&order, reference)
+    }
+}
+
+/// 以 `base` 为起点按 `step` 取整, `step` 为 0 时不处理
+fn round_step(value: Decimal, base: Decimal, step: Decimal, rounding: Rounding) -> Decimal {
+    if step.is_zero() {
+        return value;
+    }
+    let steps = (value - base) / step;
+    let steps = match rounding {
+        Rounding::Floor => steps.floor(),
+        Rounding::Ceil => steps.ceil(),
+        Rounding::Nearest => steps.round(),
+    };
+    (base + steps * step).normalize()
+}
+
+fn is_multiple(value: Decimal, base: Decimal, step: Decimal) -> bool {
+    step.is_zero() || ((value - base) % step).is_zero()
+}
+
+fn check_qty(
+    errors: &mut Vec<FilterError>,
+    order: &OrderFields,
+    min: Decimal,
+    max: Decimal,
+    step_size: Decimal,
+) {
+    let Some(qty) = order.quantity else {
+        return;
+    };
+    if (qty < min && !order.reduce_only) || (!max.is_zero() && qty > max) {
+        errors.push(FilterError::QtyRange { qty, min, max });
+    }
+    if !is_multiple(qty, min, step_size) {
+        errors.push(FilterError::StepSize { qty, step_size });
+    }
+}
+
+fn check_percent(
+    errors: &mut Vec<FilterError>,
+    price: Decimal,
+    reference: Decimal,
+    down: Decimal,
+    up: Decimal,
+) {
+    let (min, max) = (reference * down, reference * up);
+    if price < min || price > max {
+        errors.push(FilterError::PercentPrice {
+            price,
+            reference,
+            min,
+            max,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    fn dec(s: &str) -> Decimal {
+        Decimal::from_str(s).unwrap()
+    }
+
+    fn registry(market_step: &str) -> SymbolRegistry {
+        let info: ExchangeInfo = serde_json::from_value(serde_json::json!({
+            "exchangeFilters": [],
+            "rateLimits": [],
+            "serverTime": 0,
+            "symbols": [{
+                "symbol": "BTCUSDT",
+                "status": "TRADING",
+                "baseAsset": "BTC",
+                "quoteAsset": "USDT",
+                "marginAsset": "USDT",
+                "pricePrecision": 2,
+                "quantityPrecision": 3,
+                "quotePrecision": 8,
+                "filters": [
+                    {"filterType": "PRICE_FILTER", "minPrice": "0.01", "maxPrice": "1000000", "tickSize": "0.01"},
+                    {"filterType": "LOT_SIZE", "stepSize": "0.001", "maxQty": "100", "minQty": "0.001"},
+                    {"filterType": "MARKET_LOT_SIZE", "stepSize": market_step, "maxQty": "10", "minQty": "0.01"},
+                    {"filterType": "MIN_NOTIONAL", "notional": "10"}
+                ]
+            }]
+        }))
+        .unwrap();
+        SymbolRegistry::from(&info)
+    }
+
+    fn limit(price: &str, qty: &str) -> OrderFields {
+        OrderFields {
+            symbol: "BTCUSDT".into(),
+            side: OrderSide::Buy,
+            need_price: true,
+            price: Some(dec(price)),
+            quantity: Some(dec(qty)),
+            ..Default::default()
+        }
+    }
+
+    fn market(qty: &str) -> OrderFields {
+        OrderFields {
+            symbol: "BTCUSDT".into(),
+            side: OrderSide::Sell,
+            is_market: true,
+            quantity: Some(dec(qty)),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn round_down_and_to_nearest() {
+        let registry = registry("0.01");
+        let price = |p: &str, rounding| registry.round_price("BTCUSDT", dec(p), rounding).unwrap();
+        assert_eq!(price("10.126", Rounding::Floor), dec("10.12"));
+        assert_eq!(price("10.126", Rounding::Nearest), dec("10.13"));
+        assert_eq!(price("10.124", Rounding::Nearest), dec("10.12"));
+        assert_eq!(price("10.121", Rounding::Ceil), dec("10.13"));
+        assert_eq!(price("10.12", Rounding::Ceil), dec("10.12"));
+
+        let qty = |q: &str, is_market, rounding| {
+            registry
+                .round_qty("BTCUSDT", dec(q), is_market, rounding)
+                .unwrap()
+        };
+        assert_eq!(qty("1.2367", false, Rounding::Floor), dec("1.236"));
+        assert_eq!(qty("1.2367", false, Rounding::Nearest), dec("1.237"));
+        // 市价单使用 MARKET_LOT_SIZE 的步长
+        assert_eq!(qty("1.2367", true, Rounding::Floor), dec("1.23"));
+        assert_eq!(qty("1.2367", true, Rounding::Nearest), dec("1.24"));
+        assert_eq!(
+            registry.round_qty("ETHUSDT", Decimal::ONE, false, Rounding::Floor),
+            Err(FilterError::UnknownSymbol("ETHUSDT".into()))
+        );
+    }
+
+    #[test]
+    fn zero_market_step_falls_back_to_lot_size() {
+        let registry = registry("0");
+        let rules = registry.get("BTCUSDT").unwrap();
+        assert_eq!(
+            rules.round_qty(dec("1.2367"), true, Rounding::Floor),
+            dec("1.236")
+        );
+        assert_eq!(rules.step_size(), Some(dec("0.001")));
+        assert_eq!(rules.tick_size(), Some(dec("0.01")));
+    }
+
+    #[test]
+    fn min_notional() {
+        let registry = registry("0.01");
+        assert!(registry.validate(limit("100", "0.1"), None).is_ok());
+        assert_eq!(
+            registry.validate(limit("100", "0.05"), None),
+            Err(vec![FilterError::MinNotional {
+                notional: dec("5"),
+                min: dec("10"),
+            }])
+        );
+        // 只减仓的订单不检查最小名义价值
+        let reduce_only = OrderFields {
+            reduce_only: true,
+            ..limit("100", "0.05")
+        };
+        assert!(registry.validate(reduce_only, None).is_ok());
+        // 市价单用参考价估算, 没有参考价时跳过
+        assert!(registry.validate(market("0.05"), None).is_ok());
+        assert_eq!(
+            registry.validate(market("0.05"), Some(dec("100"))),
+            Err(vec![FilterError::MinNotional {
+                notional: dec("5"),
+                min: dec("10"),
+            }])
+        );
+        assert!(registry.validate(market("0.2"), Some(dec("100"))).is_ok());
+    }
+
+    #[test]
+    fn lot_size_with_market_lot_filter() {
+        let registry = registry("0.01");
+        // 限价单只检查 LOT_SIZE
+        assert!(registry.validate(limit("100", "1.234"), None).is_ok());
+        assert!(registry.validate(limit("100", "50"), None).is_ok());
+        assert_eq!(
+            registry.validate(limit("100", "1.2345"), None),
+            Err(vec![FilterError::StepSize {
+                qty: dec("1.2345"),
+                step_size: dec("0.001"),
+            }])
+        );
+        // 市价单还要满足 MARKET_LOT_SIZE
+        assert_eq!(
+            registry.validate(market("1.234"), Some(dec("100"))),
+            Err(vec![FilterError::StepSize {
+                qty: dec("1.234"),
+                step_size: dec("0.01"),
+            }])
+        );
+        assert_eq!(
+            registry.validate(market("50"), Some(dec("100"))),
+            Err(vec![FilterError::QtyRange {
+                qty: dec("50"),
+                min: dec("0.01"),
+                max: dec("10"),
+            }])
+        );
+        assert!(registry.validate(market("1.23"), Some(dec("100"))).is_ok());
+    }
+}
@@ -0,0 +1,292 @@
+use std::collections::HashSet;
+
+use tracing::{debug, warn};
+
+use crate::{
+    api::{
+        ClientError,
+        market::{AggTrade, QAggTrade},
+        trade::{
+            AccountTrade, FutureAccountTrade, FutureQueriedOrder, QAllOrders, QFutureAllOrders,
+            QFutureUserTrades, QMyTrades, QueriedOrder,
+        },
+    },
+    executor::{Executor, execute_with_retry},
+    rest::RestQuery,
+};
+
+/// 按 `fromId` 翻页下载一段时间内的归集交易
+///
+/// 第一页按 `startTime` 定位, 之后从上一页最后的ID继续, 不受 `startTime` 和 `endTime`
+/// 间隔不能超过 1 小时的限制
+pub struct AggTradeDownloader<E> {
+    executor: E,
+    page_limit: u64,
+    max_retries: usize,
+}
+
+impl<E: Executor> AggTradeDownloader<E> {
+    /// 单页最大数量
+    pub const MAX_LIMIT: u64 = 1000;
+
+    pub fn new(executor: E) -> Self {
+        Self {
+            executor,
+            page_limit: Self::MAX_LIMIT,
+            max_retries: 3,
+        }
+    }
+
+    pub fn with_page_limit(mut self, limit: u64) -> Self {
+        self.page_limit = limit.clamp(1, Self::MAX_LIMIT);
+        self
+    }
+
+    /// 限频和连接错误等可重试错误合计的最大重试次数
+    pub fn with_max_retries(mut self, max_retries: usize) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    pub fn into_inner(self) -> E {
+        self.executor
+    }
+
+    /// 下载成交时间在 `[start, end]` 内的归集交易(ms)
+    pub fn download(
+        &mut self,
+        symbol: &str,
+        start: i64,
+        end: i64,
+    ) -> Result<Vec<AggTrade>, ClientError> {
+        let mut trades = vec![];
+        self.for_each_page(symbol, start, end, |page| trades.extend_from_slice(page))?;
+        Ok(trades)
+    }
+
+    /// 逐页处理, 不在内存中保留全部成交
+    pub fn for_each_page(
+        &mut self,
+        symbol: &str,
+        start: i64,
+        end: i64,
+        mut f: impl FnMut(&[AggTrade]),
+    ) -> Result<(), ClientError> {
+        let mut query = QAggTrade {
+            symbol: symbol.to_string(),
+            from_id: None,
+            start_time: Some(start),
+            end_time: None,
+            limit: Some(self.page_limit),
+        };
+        loop {
+            let page =
+                execute_with_retry(&mut self.executor, query.clone(), self.max_retries)?.result;
+            let full = page.len() as u64 >= self.page_limit;
+            let Some(last) = page.last() else {
+                return Ok(());
+            };
+            if let (Some(from_id), Some(first)) = (query.from_id, page.first())
+                && first.agg_trade_id != from_id
+            {
+                warn!(
+                    "{symbol} agg trades jump from {from_id} to {}",
+                    first.agg_trade_id
+                );
+            }
+            let next_id = last.agg_trade_id + 1;
+            let reach_end = last.time > end;
+            let page: Vec<_> = page
+                .into_iter()
+                .filter(|t| t.time >= start && t.time <= end)
+                .collect();
+            debug!("{symbol} got {} agg trades before {next_id}", page.len());
+            if !page.is_empty() {
+                f(&page);
+            }
+            if reach_end || !full {
+                return Ok(());
+            }
+            query.start_time = None;
+            query.from_id = Some(next_id);
+        }
+    }
+}
+
+const HOUR_MS: i64 = 3_600_000;
+
+/// 限制时间间隔的历史查询, 返回 `T` 的列表
+pub trait WindowQuery<T>: RestQuery<Response = Vec<T>> {
+    /// `startTime` 和 `endTime` 的最大间隔(ms)
+    const MAX_WINDOW: i64;
+    /// 单页最大数量
+    const MAX_LIMIT: u64 = 1000;
+
+    fn window(symbol: &str, start: i64, end: i64, limit: u64) -> Self;
+    /// 用于翻页的时间
+    fn time(item: &T) -> i64;
+    /// 用于去重的ID
+    fn id(item: &T) -> i64;
+}
+
+impl WindowQuery<QueriedOrder> for QAllOrders {
+    const MAX_WINDOW: i64 = 24 * HOUR_MS;
+
+    fn window(symbol: &str, start: i64, end: i64, limit: u64) -> Self {
+        Self {
+            symbol: symbol.to_string(),
+            order_id: None,
+            start_time: Some(start),
+            end_time: Some(end),
+            limit: Some(limit),
+        }
+    }
+
+    fn time(item: &QueriedOrder) -> i64 {
+        item.time
+    }
+
+    fn id(item: &QueriedOrder) -> i64 {
+        item.order_id
+    }
+}
+
+impl WindowQuery<AccountTrade> for QMyTrades {
+    const MAX_WINDOW: i64 = 24 * HOUR_MS;
+
+    fn window(symbol: &str, start: i64, end: i64, limit: u64) -> Self {
+        Self {
+            symbol: symbol.to_string(),
+            start_time: Some(start),
+            end_time: Some(end),
+            limit: Some(limit),
+            ..Default::default()
+        }
+    }
+
+    fn time(item: &AccountTrade) -> i64 {
+        item.time
+    }
+
+    fn id(item: &AccountTrade) -> i64 {
+        item.id
+    }
+}
+
+impl WindowQuery<FutureQueriedOrder> for QFutureAllOrders {
+    const MAX_WINDOW: i64 = 7 * 24 * HOUR_MS;
+
+    fn window(symbol: &str, start: i64, end: i64, limit: u64) -> Self {
+        Self {
+            symbol: symbol.to_string(),
+            order_id: None,
+            start_time: Some(start),
+            end_time: Some(end),
+            limit: Some(limit),
+        }
+    }
+
+    fn time(item: &FutureQueriedOrder) -> i64 {
+        item.time
+    }
+
+    fn id(item: &FutureQueriedOrder) -> i64 {
+        item.order_id
+    }
+}
+
+impl WindowQuery<FutureAccountTrade> for QFutureUserTrades {
+    const MAX_WINDOW: i64 = 7 * 24 * HOUR_MS;
+
+    fn window(symbol: &str, start: i64, end: i64, limit: u64) -> Self {
+        Self {
+            symbol: symbol.to_string(),
+            start_time: Some(start),
+            end_time: Some(end),
+            limit: Some(limit),
+            ..Default::default()
+        }
+    }
+
+    fn time(item: &FutureAccountTrade) -> i64 {
+        item.time
+    }
+
+    fn id(item: &FutureAccountTrade) -> i64 {
+        item.id
+    }
+}
+
+/// 按时间窗口翻页下载订单或成交历史, 用于重启后核对状态
+///
+/// 查询区间按接口允许的最大间隔切分, 一个窗口内超过单页数量时从最后一条的时间继续
+pub struct WindowDownloader<E> {
+    executor: E,
+    page_limit: Option<u64>,
+    max_retries: usize,
+}
+
+impl<E: Executor> WindowDownloader<E> {
+    pub fn new(executor: E) -> Self {
+        Self {
+            executor,
+            page_limit: None,
+            max_retries: 3,
+        }
+    }
+
+    /// 每页数量, 默认为接口允许的最大值
+    pub fn with_page_limit(mut self, limit: u64) -> Self {
+        self.page_limit = Some(limit);
+        self
+    }
+
+    /// 限频和连接错误等可重试错误合计的最大重试次数
+    pub fn with_max_retries(mut self, max_retries: usize) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    pub fn into_inner(self) -> E {
+        self.executor
+    }
+
+    /// 下载时间在 `[start, end]` 内的记录(ms)
+    pub fn download<Q: WindowQuery<T>, T>(
+        &mut self,
+        symbol: &str,
+        start: i64,
+        end: i64,
+    ) -> Result<Vec<T>, ClientError> {
+        let limit = self
+            .page_limit
+            .unwrap_or(Q::MAX_LIMIT)
+            .clamp(1, Q::MAX_LIMIT);
+        let mut items = vec![];
+        let mut seen = HashSet::new();
+        let mut cursor = start;
+        while cursor <= end {
+            let window_end = end.min(cursor.saturating_add(Q::MAX_WINDOW - 1));
+            let query = Q::window(symbol, cursor, window_end, limit);
+            let page = execute_with_retry(&mut self.executor, query, self.max_retries)?.result;
+            let full = page.len() as u64 >= limit;
+            let last_time = page.last().map(Q::time);
+            debug!(
+                "{symbol} {} got {} items from {cursor}",
+                Q::METHOD,
+                page.len()
+            );
+            items.extend(page.into_iter().filter(|item| seen.insert(Q::id(item))));
+            cursor = match last_time {
+                // 同一毫秒的记录可能分在两页, 从最后一条的时间继续并去重
+                Some(time) if full && time > cursor => time,
+                Some(time) if full => {
+                    warn!("{symbol} more than {limit} items at {time}, some may be skipped");
+                    time + 1
+                }
+                _ => window_end + 1,
+            };
+        }
+        Ok(items)
+    }
+}
@@ -0,0 +1,220 @@
+use std::{
+    marker::PhantomData,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use serde::de::DeserializeOwned;
+use sproxy::ProxyConfig;
+use tracing::{debug, warn};
+use ws_tool::errors::WsError;
+
+use crate::{
+    api::{
+        AutoReconnectClient, ClientError,
+        user_stream::{CloseUserStream, PingUserStream, StartUserStream},
+    },
+    realtime_market::{MarketClient, StreamData, UserStreamData},
+};
+
+/// 币安要求每 60 分钟内至少延长一次 listenKey, 文档建议 30 分钟
+pub const PING_INTERVAL: Duration = Duration::from_secs(30 * 60);
+
+/// 后台线程检查是否退出的间隔
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// drop 时关闭 listenKey 最多等待的时间
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, thiserror::Error)]
+pub enum UserStreamError {
+    #[error("{0}")]
+    WsError(WsError),
+    #[error("{0}")]
+    ClientError(ClientError),
+    #[error("{0}")]
+    SerdeError(serde_json::Error),
+}
+
+impl From<WsError> for UserStreamError {
+    fn from(value: WsError) -> Self {
+        Self::WsError(value)
+    }
+}
+
+impl From<ClientError> for UserStreamError {
+    fn from(value: ClientError) -> Self {
+        Self::ClientError(value)
+    }
+}
+
+impl From<serde_json::Error> for UserStreamError {
+    fn from(value: serde_json::Error) -> Self {
+        Self::SerdeError(value)
+    }
+}
+
+/// 自动维护 listenKey 的用户数据流
+///
+/// 通过 API 客户端获取 listenKey, 在行情连接上订阅, 后台线程定时 ping.
+/// 收到 `listenKeyExpired`, ping 失败或连接断开后, 下次 [`UserDataStream::recv`]
+/// 会重新获取 listenKey 并重新连接. 合约可以使用
+/// [`FutureUserStreamData`](crate::realtime_market::FutureUserStreamData) 作为事件类型
+pub struct UserDataStream<E = UserStreamData> {
+    client: Arc<AutoReconnectClient>,
+    api_key: String,
+    url: String,
+    proxy: Option<ProxyConfig>,
+    ping_interval: Duration,
+    market: Option<MarketClient>,
+    listen_key: Arc<Mutex<String>>,
+    need_renew: Arc<AtomicBool>,
+    stop: Arc<AtomicBool>,
+    keepalive_started: bool,
+    _event: PhantomData<fn() -> E>,
+}
+
+impl<E: DeserializeOwned> UserDataStream<E> {
+    /// `url` 为组合行情地址, 如 [`SPOT_MARKET_URL`](crate::realtime_market::SPOT_MARKET_URL),
+    /// 第一次调用 [`UserDataStream::recv`] 时才会连接
+    pub fn new(
+        client: Arc<AutoReconnectClient>,
+        api_key: String,
+        url: &str,
+        proxy: Option<ProxyConfig>,
+    ) -> Self {
+        Self {
+            client,
+            api_key,
+            url: url.to_string(),
+            proxy,
+            ping_interval: PING_INTERVAL,
+            market: None,
+            listen_key: Default::default(),
+            need_renew: Default::default(),
+            stop: Default::default(),
+            keepalive_started: false,
+            _event: PhantomData,
+        }
+    }
+
+    pub fn with_ping_interval(mut self, interval: Duration) -> Self {
+        self.ping_interval = interval;
+        self
+    }
+
+    /// 当前的 listenKey, 未连接时为空
+    pub fn listen_key(&self) -> String {
+        self.listen_key.lock().unwrap().clone()
+    }
+
+    /// 获取新的 listenKey 并重新连接订阅
+    pub fn renew(&mut self) -> Result<(), UserStreamError> {
+        self.market = None;
+        let listen_key = self
+            .client
+            .query(StartUserStream {
+                api_key: self.api_key.clone(),
+            })?
+            .result
+            .listen_key;
+        let mut market = MarketClient::conn(&self.url, self.proxy.clone())?;
+        market.subscribe(vec![listen_key.clone()])?;
+        debug!("user data stream subscribed");
+        *self.listen_key.lock().unwrap() = listen_key;
+        self.need_renew.store(false, Ordering::Relaxed);
+        self.market = Some(market);
+        if !self.keepalive_started {
+            self.spawn_keepalive();
+            self.keepalive_started = true;
+        }
+        Ok(())
+    }
+
+    fn spawn_keepalive(&self) {
+        let client = self.client.clone();
+        let api_key = self.api_key.clone();
+        let listen_key = self.listen_key.clone();
+        let need_renew = self.need_renew.clone();
+        let stop = self.stop.clone();
+        let interval = self.ping_interval;
+        std::thread::spawn(move || {
+            let mut last_ping = Instant::now();
+            while !stop.load(Ordering::Relaxed) {
+                std::thread::sleep(POLL_INTERVAL);
+                if last_ping.elapsed() < interval {
+                    continue;
+                }
+                last_ping = Instant::now();
+                let listen_key = listen_key.lock().unwrap().clone();
+                if listen_key.is_empty() {
+                    continue;
+                }
+                let ping = PingUserStream {
+                    api_key: api_key.clone(),
+                    listen_key,
+                };
+                match client.query(ping) {
+                    Ok(_) => debug!("listen key extended"),
+                    Err(e) => {
+                        warn!("ping listen key failed {e}, renew on next recv");
+                        need_renew.store(true, Ordering::Relaxed);
+                    }
+                }
+            }
+        });
+    }
+
+    /// 接收下一条用户数据推送, 跳过订阅响应和旧 listenKey 的推送
+    ///
+    /// `listenKeyExpired` 事件仍会返回, 之后的调用自动换新的 listenKey
+    pub fn recv(&mut self) -> Result<E, UserStreamError> {
+        loop {
+            if self.market.is_none() || self.need_renew.load(Ordering::Relaxed) {
+                self.renew()?;
+            }
+            let msg = match self.market.as_mut().unwrap().recv() {
+                Ok(msg) => msg,
+                Err(e) => {
+                    warn!("user data stream {e}, reconnect");
+                    self.market = None;
+                    continue;
+                }
+            };
+            let data = match serde_json::from_str::<StreamData>(&msg) {
+                Ok(data) => data,
+                Err(_) => {
+                    debug!("skip non stream message {msg}");
+                    continue;
+                }
+            };
+            if data.stream != *self.listen_key.lock().unwrap() {
+                continue;
+            }
+            if data.data["e"] == "listenKeyExpired" {
+                warn!("listen key expired");
+                self.need_renew.store(true, Ordering::Relaxed);
+            }
+            break Ok(E::deserialize(data.data)?);
+        }
+    }
+}
+
+impl<E> Drop for UserDataStream<E> {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        let listen_key = std::mem::take(&mut *self.listen_key.lock().unwrap());
+        if !listen_key.is_empty() {
+            let close = CloseUserStream {
+                api_key: self.api_key.clone(),
+                listen_key,
+            };
+            if let Err(e) = self.client.query_with_timeout(close, CLOSE_TIMEOUT) {
+                debug!("close listen key failed {e}");
+            }
+        }
+    }
+}
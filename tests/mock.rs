@@ -0,0 +1,576 @@
+#![cfg(feature = "mock")]
+
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+        mpsc,
+    },
+    time::{Duration, Instant},
+};
+
+use ll_binance::{
+    api::{
+        AutoReconnectClient, Client, ClientError, ErrorCode, HmacSigner, RateLimit, RateLimitMode,
+        RateLimiter, RequestSigner, ServerMessage,
+        common::{Ping, QServerTime},
+        market::{AggTrade, QKline},
+        trade::{CancelReplaceMode, CancelReplaceOrder, CancelReplaceStatus, OrderSpec, QMyTrades},
+    },
+    executor::{Executor, Fallback},
+    kline_history::{KlineDownloader, KlineGap},
+    mock::{Fault, MockReply, MockServer},
+    realtime_market::UserStreamData,
+    risk::{RiskError, RiskGuard, RiskLimits},
+    trade_history::{AggTradeDownloader, WindowDownloader},
+};
+use serde_json::json;
+
+fn auto_client(server: &MockServer) -> AutoReconnectClient {
+    AutoReconnectClient::client(&server.url(), 3, None, 5000, None)
+}
+
+#[test]
+fn canned_responses() {
+    let server = MockServer::start().unwrap();
+    let mut client = Client::conn(&server.url(), None, 5000).unwrap();
+    client.query(Ping).unwrap();
+    let time = client.query(QServerTime).unwrap().result.time;
+    assert!(time > 0);
+    let order = OrderSpec {
+        symbol: "BTCUSDT".into(),
+        new_client_order_id: Some("my-order".into()),
+        ..Default::default()
+    };
+    let resp = client.query(order).unwrap();
+    assert_eq!(resp.result.order_id(), 1);
+}
+
+#[test]
+fn scripted_and_error_responses() {
+    let server = MockServer::start().unwrap();
+    server.on("time", |_| MockReply::Ok(json!({ "serverTime": 42 })));
+    let client = auto_client(&server);
+    assert_eq!(client.query(QServerTime).unwrap().result.time, 42);
+
+    server.inject(Fault::Error {
+        status: 400,
+        code: -1021,
+        msg: "Timestamp for this request is outside of the recvWindow.".into(),
+    });
+    let err = client.query(Ping).unwrap_err();
+    let api_err = err.api_error().unwrap();
+    assert_eq!(api_err.error_code(), ErrorCode::InvalidTimestamp);
+    assert!(err.is_retryable());
+}
+
+#[test]
+fn reordered_responses_and_pings() {
+    let server = MockServer::start().unwrap();
+    let client = Arc::new(auto_client(&server));
+    client.query(Ping).unwrap();
+    server.inject(Fault::Reorder(4));
+    server.inject(Fault::Ping);
+    let handles: Vec<_> = (0..4)
+        .map(|_| {
+            let client = client.clone();
+            std::thread::spawn(move || client.query(QServerTime).map(|r| r.id))
+        })
+        .collect();
+    let mut ids: Vec<_> = handles
+        .into_iter()
+        .map(|h| h.join().unwrap().unwrap())
+        .collect();
+    ids.sort();
+    ids.dedup();
+    assert_eq!(ids.len(), 4);
+}
+
+#[test]
+fn reconnect_after_disconnect() {
+    let server = MockServer::start().unwrap();
+    let client = auto_client(&server);
+    client.query(Ping).unwrap();
+    server.inject(Fault::Disconnect);
+    client.query(Ping).unwrap();
+    assert_eq!(server.connection_count(), 2);
+
+    server.disconnect_all();
+    std::thread::sleep(Duration::from_millis(300));
+    client.query(Ping).unwrap();
+    assert_eq!(server.connection_count(), 3);
+}
+
+#[test]
+fn order_not_resent_after_disconnect() {
+    let server = MockServer::start().unwrap();
+    let client = auto_client(&server);
+    client.query(Ping).unwrap();
+    server.inject(Fault::Disconnect);
+    let err = client
+        .query(OrderSpec {
+            symbol: "BTCUSDT".into(),
+            ..Default::default()
+        })
+        .unwrap_err();
+    assert!(err.is_unknown_outcome());
+    let orders = server
+        .requests()
+        .iter()
+        .filter(|r| r.method == "order.place")
+        .count();
+    assert_eq!(orders, 1);
+    client.query(Ping).unwrap();
+    assert_eq!(server.connection_count(), 2);
+}
+
+#[test]
+fn reconnect_respects_query_deadline() {
+    let server = MockServer::start().unwrap();
+    let client = AutoReconnectClient::client(&server.url(), 100, None, 5000, None);
+    client.query(Ping).unwrap();
+    server.stop_accepting();
+    std::thread::sleep(Duration::from_millis(50));
+    server.inject(Fault::Disconnect);
+    let start = Instant::now();
+    let err = client
+        .query_with_timeout(Ping, Duration::from_millis(300))
+        .unwrap_err();
+    assert!(matches!(err, ClientError::Timeout), "{err}");
+    assert!(start.elapsed() < Duration::from_millis(600));
+}
+
+#[test]
+fn failed_batch_releases_pending_weight() {
+    let server = MockServer::start().unwrap();
+    let limiter = Arc::new(RateLimiter::new(
+        vec![RateLimit {
+            rate_limit_type: "REQUEST_WEIGHT".into(),
+            interval: "MINUTE".into(),
+            interval_num: 1,
+            limit: 6000,
+            count: 0,
+        }],
+        RateLimitMode::Reject,
+    ));
+    let mut client = Client::conn(&server.url(), None, 5000)
+        .unwrap()
+        .with_rate_limiter(limiter.clone());
+    server.inject(Fault::Error {
+        status: 400,
+        code: -1100,
+        msg: "illegal characters".into(),
+    });
+    let err = client.batch_query(5, vec![Ping; 5]).unwrap_err();
+    assert!(matches!(err, ClientError::ApiError(_)));
+    // 其余请求的响应被丢弃, 预占的权重全部释放
+    client.query(Ping).unwrap();
+    assert_eq!(limiter.usage()[0].count, 6);
+}
+
+#[test]
+fn timeout_discards_late_response() {
+    let server = MockServer::start().unwrap();
+    let client = auto_client(&server);
+    server.inject(Fault::Delay(Duration::from_millis(500)));
+    let start = Instant::now();
+    let err = client
+        .query_with_timeout(Ping, Duration::from_millis(100))
+        .unwrap_err();
+    assert!(matches!(err, ClientError::Timeout));
+    assert!(err.is_unknown_outcome() && !err.is_retryable());
+    assert!(start.elapsed() < Duration::from_millis(500));
+    client.query(QServerTime).unwrap();
+}
+
+#[test]
+fn sync_client_timeout_discards_late_response() {
+    let server = MockServer::start().unwrap();
+    let mut client = Client::conn(&server.url(), None, 5000).unwrap();
+    server.inject(Fault::Delay(Duration::from_millis(300)));
+    let start = Instant::now();
+    let err = client
+        .query_with_timeout(Ping, Duration::from_millis(100))
+        .unwrap_err();
+    assert!(matches!(err, ClientError::Timeout));
+    assert!(start.elapsed() < Duration::from_millis(300));
+    server.on("time", |_| MockReply::Ok(json!({ "serverTime": 7 })));
+    assert_eq!(client.query(QServerTime).unwrap().result.time, 7);
+}
+
+#[test]
+fn signed_order_without_session() {
+    let server = MockServer::start().unwrap();
+    let signer = RequestSigner::new("key".into(), Arc::new(HmacSigner::new("secret")));
+    let mut client = Client::conn(&server.url(), None, 5000)
+        .unwrap()
+        .with_signer(signer);
+    client
+        .query(OrderSpec {
+            symbol: "BTCUSDT".into(),
+            ..Default::default()
+        })
+        .unwrap();
+    let req = server.requests().pop().unwrap();
+    assert_eq!(req.method, "order.place");
+    assert_eq!(req.params["apiKey"], "key");
+    assert_eq!(req.params["signature"].as_str().unwrap().len(), 64);
+}
+
+#[test]
+fn user_stream_events_routed_to_handler() {
+    let server = MockServer::start().unwrap();
+    let (tx, rx) = mpsc::channel();
+    let tx = std::sync::Mutex::new(tx);
+    let client = auto_client(&server).with_event_handler(move |event| {
+        tx.lock().unwrap().send(event).ok();
+    });
+    let sub = client.subscribe_user_stream().unwrap();
+    assert_eq!(sub.result.subscription_id, 0);
+    server.inject(Fault::Event(json!({
+        "e": "listenKeyExpired",
+        "E": 1,
+        "listenKey": "",
+    })));
+    client.query(Ping).unwrap();
+    let ServerMessage::Event(event) = rx.recv_timeout(Duration::from_secs(1)).unwrap() else {
+        panic!("expect event");
+    };
+    assert!(matches!(
+        event.parse::<UserStreamData>().unwrap(),
+        UserStreamData::ListenKeyExpired(_)
+    ));
+
+    server.inject(Fault::Disconnect);
+    client.query(Ping).unwrap();
+    let methods: Vec<_> = server.requests().into_iter().map(|r| r.method).collect();
+    assert_eq!(
+        methods
+            .iter()
+            .filter(|m| *m == "userDataStream.subscribe")
+            .count(),
+        2
+    );
+}
+
+#[test]
+fn unsolicited_frames_do_not_cancel_request() {
+    let server = MockServer::start().unwrap();
+    let (tx, rx) = mpsc::channel();
+    let tx = std::sync::Mutex::new(tx);
+    let mut client = Client::conn(&server.url(), None, 5000)
+        .unwrap()
+        .with_event_handler(move |msg| {
+            tx.lock().unwrap().send(msg).ok();
+        });
+    server.inject(Fault::Frame(json!({
+        "id": null,
+        "status": 400,
+        "error": { "code": -1000, "msg": "malformed request" },
+    })));
+    server.on("time", |_| MockReply::Ok(json!({ "serverTime": 7 })));
+    assert_eq!(client.query(QServerTime).unwrap().result.time, 7);
+    server.inject(Fault::Event(
+        json!({ "e": "eventStreamTerminated", "E": 1 }),
+    ));
+    client.query(Ping).unwrap();
+
+    let msgs: Vec<_> = rx.try_iter().collect();
+    assert!(matches!(&msgs[0], ServerMessage::Error(e) if e.error.code == -1000));
+    assert!(matches!(&msgs[1], ServerMessage::Event(e) if e.event["e"] == "eventStreamTerminated"));
+}
+
+#[test]
+fn fallback_to_secondary_transport() {
+    let primary = MockServer::start().unwrap();
+    let secondary = MockServer::start().unwrap();
+    secondary.on("time", |_| MockReply::Ok(json!({ "serverTime": 2 })));
+    let mut executor = Fallback::new(
+        AutoReconnectClient::client(&primary.url(), 1, None, 5000, None),
+        auto_client(&secondary),
+        Duration::from_secs(60),
+    );
+    primary.inject(Fault::Disconnect);
+    primary.inject(Fault::Disconnect);
+    assert_eq!(executor.execute(QServerTime).unwrap().result.time, 2);
+    assert!(executor.is_degraded());
+
+    // 下单请求不会在备用通道重发
+    let mut executor = Fallback::new(
+        AutoReconnectClient::client(&primary.url(), 1, None, 5000, None),
+        auto_client(&secondary),
+        Duration::from_secs(60),
+    );
+    primary.inject(Fault::Disconnect);
+    primary.inject(Fault::Disconnect);
+    let order = OrderSpec {
+        symbol: "BTCUSDT".into(),
+        ..Default::default()
+    };
+    assert!(matches!(
+        executor.execute(order),
+        Err(ClientError::WsError(_))
+    ));
+    assert!(
+        secondary
+            .requests()
+            .iter()
+            .all(|r| r.method != "order.place")
+    );
+}
+
+#[test]
+fn kline_download_pages_and_reports_gaps() {
+    let server = MockServer::start().unwrap();
+    const MIN: i64 = 60_000;
+    server.on("klines", |req| {
+        let start = req.params["startTime"].as_i64().unwrap();
+        let end = req.params["endTime"].as_i64().unwrap();
+        let limit = req.params["limit"].as_i64().unwrap();
+        let first = (start + MIN - 1) / MIN * MIN;
+        let klines: Vec<_> = (first..=end)
+            .step_by(MIN as usize)
+            // 缺少第 5 根
+            .filter(|t| *t != 4 * MIN)
+            .take(limit as usize)
+            .map(|t| {
+                json!([
+                    t,
+                    "1",
+                    "2",
+                    "0.5",
+                    "1.5",
+                    "10",
+                    t + MIN - 1,
+                    "15",
+                    3,
+                    "4",
+                    "6",
+                    "0"
+                ])
+            })
+            .collect();
+        MockReply::Ok(json!(klines))
+    });
+    let mut downloader = KlineDownloader::new(auto_client(&server)).with_page_limit(3);
+    let history = downloader
+        .download::<QKline>("BTCUSDT", Default::default(), 0, 10 * MIN)
+        .unwrap();
+    let opens: Vec<_> = history.klines.iter().map(|k| k.open_time / MIN).collect();
+    assert_eq!(opens, vec![0, 1, 2, 3, 5, 6, 7, 8, 9, 10]);
+    assert_eq!(
+        history.gaps,
+        vec![KlineGap {
+            start: 4 * MIN,
+            end: 5 * MIN,
+            missing: 1
+        }]
+    );
+    assert_eq!(server.requests().len(), 4);
+}
+
+#[test]
+fn agg_trades_page_by_from_id() {
+    let server = MockServer::start().unwrap();
+    // 每秒一笔成交, ID 为秒数
+    server.on("trades.aggregate", |req| {
+        let from = match req.params.get("fromId") {
+            Some(id) => id.as_i64().unwrap(),
+            None => (req.params["startTime"].as_i64().unwrap() + 999) / 1000,
+        };
+        let limit = req.params["limit"].as_i64().unwrap();
+        let trades: Vec<_> = (from..from + limit)
+            .map(|id| AggTrade {
+                agg_trade_id: id,
+                price: 1.into(),
+                qty: 1.into(),
+                first_trade_id: id,
+                last_trade_id: id,
+                time: id * 1000,
+                is_maker: false,
+                is_best_match: true,
+            })
+            .collect();
+        MockReply::Ok(json!(trades))
+    });
+    let mut downloader = AggTradeDownloader::new(auto_client(&server)).with_page_limit(4);
+    let trades = downloader.download("BTCUSDT", 1500, 10_000).unwrap();
+    let ids: Vec<_> = trades.iter().map(|t| t.agg_trade_id).collect();
+    assert_eq!(ids, (2..=10).collect::<Vec<_>>());
+    let requests = server.requests();
+    assert_eq!(requests.len(), 3);
+    assert_eq!(requests[1].params["fromId"], 6);
+    assert!(requests[1].params.get("startTime").is_none());
+}
+
+#[test]
+fn risk_guard_rejects_and_kill_switch_cancels() {
+    let server = MockServer::start().unwrap();
+    let cancels = Arc::new(AtomicUsize::new(0));
+    let cancels_c = cancels.clone();
+    server.on("openOrders.cancelAll", move |_| {
+        match cancels_c.fetch_add(1, Ordering::Relaxed) {
+            0 => MockReply::err(
+                401,
+                -2015,
+                "Invalid API-key, IP, or permissions for action.",
+            ),
+            _ => MockReply::err(400, -2011, "Unknown order sent."),
+        }
+    });
+    let signer = RequestSigner::new("key".into(), Arc::new(HmacSigner::new("secret")));
+    let client = Client::conn(&server.url(), None, 5000)
+        .unwrap()
+        .with_signer(signer);
+    let limits = RiskLimits {
+        max_notional: Some(1000.into()),
+        price_band: Some("0.05".parse().unwrap()),
+        max_guarded_orders: Some(1),
+        ..Default::default()
+    };
+    let mut guard = RiskGuard::new(client, limits);
+    guard.set_price("BTCUSDT", 100.into());
+    let order = |price: i64, qty: i64| OrderSpec {
+        symbol: "BTCUSDT".into(),
+        price: Some(price.into()),
+        quantity: Some(qty.into()),
+        ..Default::default()
+    };
+    assert!(matches!(
+        guard.place_order(order(100, 20)),
+        Err(RiskError::MaxNotional { .. })
+    ));
+    assert!(matches!(
+        guard.place_order(order(120, 1)),
+        Err(RiskError::PriceBand { .. })
+    ));
+    guard.place_order(order(101, 1)).unwrap();
+    assert!(matches!(
+        guard.place_order(order(101, 1)),
+        Err(RiskError::MaxGuardedOrders { open: 1, max: 1 })
+    ));
+    // 撤单失败时保留订单, 再次调用时重试
+    let errors = guard.kill();
+    assert_eq!(errors.len(), 1);
+    assert!(errors[0].is_auth());
+    assert_eq!(guard.guarded_orders(), 1);
+    // 没有挂单(-2011)视为已撤销
+    assert!(guard.kill().is_empty());
+    assert_eq!(guard.guarded_orders(), 0);
+    assert!(matches!(
+        guard.place_order(order(101, 1)),
+        Err(RiskError::KillSwitch)
+    ));
+    let methods: Vec<_> = server.requests().into_iter().map(|r| r.method).collect();
+    assert_eq!(
+        methods,
+        vec![
+            "order.place",
+            "openOrders.cancelAll",
+            "openOrders.cancelAll"
+        ]
+    );
+}
+
+#[test]
+fn my_trades_split_by_window() {
+    const HOUR: i64 = 3_600_000;
+    let server = MockServer::start().unwrap();
+    // 每小时一笔成交, ID 为小时数
+    server.on("myTrades", |req| {
+        let start = req.params["startTime"].as_i64().unwrap();
+        let end = req.params["endTime"].as_i64().unwrap();
+        assert!(end - start < 24 * HOUR);
+        let limit = req.params["limit"].as_u64().unwrap() as usize;
+        let trades: Vec<_> = (0..48)
+            .filter(|id| (start..=end).contains(&(id * HOUR)))
+            .take(limit)
+            .map(|id| {
+                json!({
+                    "symbol": "BTCUSDT",
+                    "id": id,
+                    "orderId": id,
+                    "price": "1",
+                    "qty": "1",
+                    "quoteQty": "1",
+                    "commission": "0",
+                    "commissionAsset": "BNB",
+                    "time": id * HOUR,
+                    "isBuyer": true,
+                    "isMaker": false,
+                    "isBestMatch": true,
+                })
+            })
+            .collect();
+        MockReply::Ok(json!(trades))
+    });
+    let signer = RequestSigner::new("key".into(), Arc::new(HmacSigner::new("secret")));
+    let client = Client::conn(&server.url(), None, 5000)
+        .unwrap()
+        .with_signer(signer);
+    let mut downloader = WindowDownloader::new(client).with_page_limit(10);
+    let trades = downloader
+        .download::<QMyTrades, _>("BTCUSDT", 0, 48 * HOUR - 1)
+        .unwrap();
+    let ids: Vec<_> = trades.iter().map(|t| t.id).collect();
+    assert_eq!(ids, (0..48).collect::<Vec<_>>());
+    assert_eq!(server.requests().len(), 6);
+}
+
+#[test]
+fn cancel_replace_sends_flattened_order() {
+    let server = MockServer::start().unwrap();
+    server.on("order.cancelReplace", |_| {
+        MockReply::Ok(json!({
+            "cancelResult": "SUCCESS",
+            "newOrderResult": "SUCCESS",
+            "cancelResponse": {
+                "symbol": "BTCUSDT",
+                "origClientOrderId": "old",
+                "orderId": 1,
+                "orderListId": -1,
+                "clientOrderId": "cancel",
+                "transactTime": 1,
+                "price": "100",
+                "origQty": "1",
+                "executedQty": "0",
+                "cummulativeQuoteQty": "0",
+                "status": "CANCELED",
+                "timeInForce": "GTC",
+                "type": "LIMIT",
+                "side": "BUY",
+            },
+            "newOrderResponse": {
+                "symbol": "BTCUSDT",
+                "orderId": 2,
+                "orderListId": -1,
+                "clientOrderId": "new",
+                "transactTime": 1,
+            },
+        }))
+    });
+    let signer = RequestSigner::new("key".into(), Arc::new(HmacSigner::new("secret")));
+    let mut client = Client::conn(&server.url(), None, 5000)
+        .unwrap()
+        .with_signer(signer);
+    let resp = client
+        .query(CancelReplaceOrder {
+            cancel_replace_mode: CancelReplaceMode::AllowFailure,
+            cancel_order_id: Some(1),
+            order: OrderSpec {
+                symbol: "BTCUSDT".into(),
+                new_client_order_id: Some("new".into()),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .unwrap()
+        .result;
+    assert_eq!(resp.cancel_result, CancelReplaceStatus::Success);
+    assert_eq!(resp.new_order_result, CancelReplaceStatus::Success);
+    let req = server.requests().pop().unwrap();
+    assert_eq!(req.params["cancelReplaceMode"], "ALLOW_FAILURE");
+    assert_eq!(req.params["cancelOrderId"], 1);
+    assert_eq!(req.params["symbol"], "BTCUSDT");
+    assert_eq!(req.params["newClientOrderId"], "new");
+}